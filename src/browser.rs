@@ -0,0 +1,60 @@
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
+
+use crate::sample_pool;
+
+/// Maximum number of files a search returns, so searching a whole drive stays responsive
+const MAX_RESULTS: usize = 500;
+
+/// The subfolders and supported files in a folder for the sample browser, folders first. Hidden
+/// entries are skipped.
+pub fn list(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let (mut folders, mut files): (Vec<_>, Vec<_>) = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .filter(|path| path.is_dir() || sample_pool::is_supported(path))
+        .partition(|path| path.is_dir());
+
+    folders.sort();
+    files.sort();
+    folders.extend(files);
+    folders
+}
+
+/// Supported files in a folder and its subfolders whose name contains all words of the query,
+/// ignoring case. The search stops once enough files were found.
+pub fn search(dir: &Path, query: &str) -> Vec<PathBuf> {
+    let words = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    let mut results = vec![];
+    sample_pool::walk_supported_files(dir, &mut |path| {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if words.iter().all(|word| name.contains(word)) {
+            results.push(path);
+        }
+
+        if results.len() < MAX_RESULTS {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        }
+    });
+    results
+}
@@ -1,3 +1,4 @@
 pub mod editor;
 pub mod visualizer;
+pub mod waveform;
 pub use editor::*;
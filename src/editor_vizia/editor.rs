@@ -1,7 +1,9 @@
 #![allow(unused)]
 use std::{
+    collections::HashMap,
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
 };
 
 use nih_plug_vizia::{create_vizia_editor, ViziaState, ViziaTheming};
@@ -10,9 +12,36 @@ use nih_plug::prelude::*;
 use nih_plug_vizia::vizia::prelude::*;
 use nih_plug_vizia::widgets::*;
 
-use crate::{NihSamplerParams, ThreadMessage};
+use crate::{
+    browser, embed, formats,
+    kit::{HiHat, Kit, Pad, Zone},
+    library,
+    midi_learn::{learnable_params, LearnTarget, LearnableParam, Learned},
+    modulation::{Curve, ModDestination, ModRoute, ModSource},
+    poly::Mpe,
+    relink,
+    routing::Routing,
+    sample_pool::{self, SampleStatus},
+    templates::{self, Template},
+    NihSamplerParams, ThreadMessage,
+};
+
+use super::{
+    visualizer::{Visualizer, VisualizerData},
+    waveform::{Waveform, WaveformData, WaveformEvent},
+};
+
+/// How often captured MIDI events are picked up from the audio thread
+const LEARN_POLL_INTERVAL: Duration = Duration::from_millis(20);
 
-use super::visualizer::{Visualizer, VisualizerData};
+/// Velocity samples are auditioned at until it's changed
+const DEFAULT_AUDITION_VELOCITY: u8 = 100;
+
+/// File extensions of saved kits
+const KIT_EXTENSIONS: &[&str] = &["toml", "json"];
+
+/// Threshold used when choking by poly aftertouch is turned on
+const DEFAULT_CHOKE_PRESSURE: f32 = 0.5;
 
 #[derive(Lens)]
 struct Data {
@@ -20,13 +49,120 @@ struct Data {
     producer: Arc<Mutex<rtrb::Producer<ThreadMessage>>>,
     debug: String,
     visualizer: Arc<VisualizerData>,
+    waveform: Arc<WaveformData>,
+    sample_status: Arc<Mutex<HashMap<PathBuf, SampleStatus>>>,
+    /// Whether folders added with "Add Folder" are rescanned for new files on reload
+    remember_folders: bool,
+    /// Problems from the last import, shown under the settings
+    import_report: String,
+    /// Why the last dropped or picked files couldn't be loaded, shown above the sample list until
+    /// it's clicked
+    load_report: String,
+    templates: Vec<Template>,
+    /// The template used for picking pads and creating or remapping kits
+    template_index: usize,
+    template_name: String,
+    /// The parameters CCs can be mapped to, in the order learn targets refer to them
+    learnable_params: Vec<LearnableParam>,
+    learning: Option<LearnTarget>,
+    learn_status: String,
+    /// The sample whose settings are shown in the sample list, and its length in seconds
+    selected_sample: Option<PathBuf>,
+    selected_duration: Option<f32>,
+    audition_velocity: u8,
+    /// The folder shown in the sample browser and its entries, or the search results in it
+    browser_dir: Option<PathBuf>,
+    browser_query: String,
+    browser_entries: Vec<PathBuf>,
+    favourite_folders: Vec<PathBuf>,
+    preview_on_hover: bool,
 }
 
 #[derive(Clone)]
 enum AppEvent {
     OpenFilePicker,
-    LoadSample(PathBuf),
+    OpenFolderPicker,
+    LoadFolder(PathBuf),
+    ForgetFolder(PathBuf),
+    ToggleRememberFolders,
+    SaveKit,
+    LoadKit,
+    LoadKitFile(PathBuf),
+    /// Applies a pad from a loaded kit to this instance
+    ApplyPad(Pad),
+    Import,
+    ImportFile(PathBuf),
+    SetImportReport(String),
+    ExportSfz,
+    /// Selects the next or previous template
+    CycleTemplate(isize),
+    /// Switches to the next or previous pad in the selected template
+    CyclePad(isize),
+    /// Creates a kit with all pads of the selected template, including this instance's pad
+    NewKit,
+    /// Remaps a kit file to the selected template
+    RemapKit,
+    /// Captures the next note or CC for the target, or stops capturing if it already is
+    ToggleLearn(LearnTarget),
+    Learned(Learned),
+    ClearCcMapping(usize),
+    /// Turns the pad into a hi-hat or back into a regular pad
+    ToggleHiHat,
+    /// Enables choking by poly pressure, or changes the threshold by a number of steps
+    ToggleChokePressure,
+    StepChokePressure(f32),
+    /// Switches the MIDI report of picked samples between off, notes and CCs
+    CycleSampleReport,
+    StepSampleReportChannel(i32),
+    CycleRouting,
+    /// Turns MPE mode on or off, or changes its pitch bend range by a number of semitones
+    ToggleMpe,
+    StepMpeBendRange(f32),
+    StepOutputTranspose(i32),
+    AddModRoute,
+    RemoveModRoute(usize),
+    /// Switches a route to the next source, destination or curve
+    CycleModSource(usize),
+    CycleModDestination(usize),
+    CycleModCurve(usize),
+    /// Changes a route's CC number or amount by a number of steps
+    StepModCc(usize, i32),
+    StepModAmount(usize, f32),
+    /// Asks for a kit file to add to the bank program changes switch between
+    PickBankKit,
+    AddBankKit(PathBuf),
+    RemoveBankKit(usize),
+    LoadSamples(Vec<PathBuf>),
+    /// Shows why files couldn't be loaded, or hides the message when it's empty
+    SetLoadReport(String),
     RemoveSample(PathBuf),
+    /// Shows the settings of a sample, or hides them if they already are
+    SelectSample(PathBuf),
+    /// Changes one of a sample's settings by a number of steps
+    StepZone(PathBuf, ZoneSetting, f32),
+    ToggleReverse(PathBuf),
+    /// Plays a sample through the audio thread at the audition velocity
+    Audition(PathBuf),
+    StepAuditionVelocity(i32),
+    PickBrowserFolder,
+    BrowseTo(PathBuf),
+    BrowseUp,
+    /// Searches the browser's folder and its subfolders, or lists the folder for an empty query
+    SearchBrowser(String),
+    SetBrowserEntries(Vec<PathBuf>),
+    ToggleFavouriteFolder,
+    TogglePreviewOnHover,
+    /// Decodes a file from the browser in the background and plays it
+    Preview(PathBuf),
+    HoverPreview(PathBuf),
+    /// Adds a file or a folder to the sample list
+    AddPath(PathBuf),
+    /// Handles a file or folder dropped on the editor: kits are loaded, other samplers' files are
+    /// imported and everything else is added to the sample list
+    OpenPath(PathBuf),
+    /// Asks for a folder to search for the missing samples
+    RelinkMissingSamples,
+    RelinkSamples(Vec<(PathBuf, PathBuf)>),
 }
 
 impl Model for Data {
@@ -35,23 +171,435 @@ impl Model for Data {
             AppEvent::OpenFilePicker => {
                 cx.spawn(|cx_proxy| {
                     if let Some(paths) = rfd::FileDialog::new().pick_files() {
-                        for path in paths {
-                            cx_proxy.emit(AppEvent::LoadSample(path));
+                        cx_proxy.emit(AppEvent::LoadSamples(paths));
+                    }
+                });
+            }
+            AppEvent::OpenFolderPicker => {
+                cx.spawn(|cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        cx_proxy.emit(AppEvent::LoadFolder(path));
+                    }
+                });
+            }
+            AppEvent::LoadFolder(path) => {
+                self.debug = format!("loading folder: {path:?}");
+                if let Err(e) = self
+                    .producer
+                    .lock()
+                    .unwrap()
+                    .push(ThreadMessage::LoadFolder {
+                        path: path.clone(),
+                        remember: self.remember_folders,
+                    })
+                {
+                    self.debug = e.to_string();
+                }
+            }
+            AppEvent::ForgetFolder(path) => {
+                if let Err(e) = self
+                    .producer
+                    .lock()
+                    .unwrap()
+                    .push(ThreadMessage::ForgetFolder(path.clone()))
+                {
+                    self.debug = e.to_string();
+                }
+            }
+            AppEvent::ToggleRememberFolders => {
+                self.remember_folders = !self.remember_folders;
+            }
+            AppEvent::SaveKit => {
+                let pad = Pad::from_params(&self.params);
+                cx.spawn(move |_| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Kit", KIT_EXTENSIONS)
+                        .set_file_name("kit.toml")
+                        .save_file()
+                    {
+                        let kit = Kit {
+                            name: path
+                                .file_stem()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .to_string(),
+                            template: None,
+                            pads: vec![pad],
+                        };
+                        if let Err(e) = kit.save(&path) {
+                            nih_error!("failed to save kit to {path:?}: {e}");
                         }
                     }
                 });
             }
-            AppEvent::LoadSample(path) => {
-                self.debug = format!("loading: {path:?}");
+            AppEvent::LoadKit => {
+                cx.spawn(|cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Kit", KIT_EXTENSIONS)
+                        .pick_file()
+                    {
+                        cx_proxy.emit(AppEvent::LoadKitFile(path));
+                    }
+                });
+            }
+            AppEvent::LoadKitFile(path) => {
+                let note = self.params.note.value() as u8;
+                let path = path.clone();
+                cx.spawn(move |cx_proxy| match Kit::load(&path) {
+                    Ok(kit) => match kit.pad_for_note(note) {
+                        Some(pad) => cx_proxy.emit(AppEvent::ApplyPad(pad.clone())),
+                        None => cx_proxy.emit(AppEvent::SetLoadReport(format!(
+                            "{path:?} has no pad for note {note}"
+                        ))),
+                    },
+                    Err(e) => {
+                        nih_error!("failed to load kit from {path:?}: {e}");
+                        cx_proxy.emit(AppEvent::SetLoadReport(format!(
+                            "failed to load kit from {path:?}: {e}"
+                        )));
+                    }
+                });
+            }
+            AppEvent::Import => {
+                cx.spawn(|cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Sampler instrument", formats::IMPORT_EXTENSIONS)
+                        .pick_file()
+                    {
+                        cx_proxy.emit(AppEvent::ImportFile(path));
+                    }
+                });
+            }
+            AppEvent::ImportFile(path) => {
+                let note = self.params.note.value() as u8;
+                let path = path.clone();
+                cx.spawn(move |cx_proxy| match formats::import(&path) {
+                    Ok((kit, warnings)) => {
+                        for warning in &warnings {
+                            nih_warn!("{path:?}: {warning}");
+                        }
+                        cx_proxy.emit(AppEvent::SetImportReport(warnings.join("\n")));
+                        if let Some(pad) = kit.pad_for_note(note) {
+                            cx_proxy.emit(AppEvent::ApplyPad(pad.clone()));
+                        }
+                    }
+                    Err(e) => {
+                        cx_proxy.emit(AppEvent::SetImportReport(e));
+                    }
+                });
+            }
+            AppEvent::SetImportReport(report) => {
+                self.import_report = report.clone();
+            }
+            AppEvent::ExportSfz => {
+                let pad = Pad::from_params(&self.params);
+                cx.spawn(move |_| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("SFZ", &["sfz"])
+                        .set_file_name("kit.sfz")
+                        .save_file()
+                    {
+                        let kit = Kit {
+                            name: path
+                                .file_stem()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .to_string(),
+                            template: None,
+                            pads: vec![pad],
+                        };
+                        if let Err(e) = formats::sfz::export(&kit, &path) {
+                            nih_error!("failed to export {path:?}: {e}");
+                        }
+                    }
+                });
+            }
+            AppEvent::CycleTemplate(step) => {
+                self.template_index = (self.template_index as isize + step)
+                    .rem_euclid(self.templates.len() as isize)
+                    as usize;
+                let template = &self.templates[self.template_index];
+                self.template_name = template.name.clone();
+
+                if let Some(pad) = template.pad_for_note(self.params.note.value() as u8) {
+                    *self.params.pad_name.lock().unwrap() = pad.name.clone();
+                }
+            }
+            AppEvent::CyclePad(step) => {
+                let template = &self.templates[self.template_index];
+                if template.pads.is_empty() {
+                    return;
+                }
+
+                let note = self.params.note.value() as u8;
+                let index = match template.pads.iter().position(|pad| pad.note == note) {
+                    Some(index) => (index as isize + step).rem_euclid(template.pads.len() as isize),
+                    None => 0,
+                };
+                let pad = template.pads[index as usize].clone();
+                *self.params.pad_name.lock().unwrap() = pad.name;
+                set_param(cx, &self.params.note, pad.note as i32);
+            }
+            AppEvent::NewKit => {
+                let template = self.templates[self.template_index].clone();
+                let mut pad = Pad::from_params(&self.params);
+                cx.spawn(move |_| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Kit", KIT_EXTENSIONS)
+                        .set_file_name("kit.toml")
+                        .save_file()
+                    {
+                        let name = path
+                            .file_stem()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string();
+                        let mut kit = template.new_kit(name);
+                        match kit.pads.iter_mut().find(|kit_pad| kit_pad.note == pad.note) {
+                            Some(kit_pad) => {
+                                pad.name = kit_pad.name.clone();
+                                *kit_pad = pad;
+                            }
+                            None => kit.pads.push(pad),
+                        }
+
+                        if let Err(e) = kit.save(&path) {
+                            nih_error!("failed to save kit to {path:?}: {e}");
+                        }
+                    }
+                });
+            }
+            AppEvent::RemapKit => {
+                let templates = self.templates.clone();
+                let template_index = self.template_index;
+                cx.spawn(move |cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Kit", KIT_EXTENSIONS)
+                        .pick_file()
+                    {
+                        let result = Kit::load(&path).and_then(|mut kit| {
+                            let warnings = templates[template_index].remap(&mut kit, &templates);
+                            kit.save(&path)?;
+                            Ok(warnings)
+                        });
+                        match result {
+                            Ok(warnings) => {
+                                cx_proxy.emit(AppEvent::SetImportReport(warnings.join("\n")))
+                            }
+                            Err(e) => cx_proxy.emit(AppEvent::SetImportReport(e)),
+                        }
+                    }
+                });
+            }
+            AppEvent::ToggleLearn(target) => {
+                self.learning = if self.learning == Some(*target) {
+                    self.learn_status = String::new();
+                    None
+                } else {
+                    self.learn_status = match target {
+                        LearnTarget::Note => "Play a note...".into(),
+                        LearnTarget::Param(param) => {
+                            format!("Move a CC for {}...", self.learnable_params[*param].name)
+                        }
+                    };
+                    Some(*target)
+                };
+
+                if let Err(e) = self
+                    .producer
+                    .lock()
+                    .unwrap()
+                    .push(ThreadMessage::Learn(self.learning))
+                {
+                    self.debug = e.to_string();
+                }
+            }
+            AppEvent::Learned(learned) => match *learned {
+                Learned::Note(note) => {
+                    self.learning = None;
+                    self.learn_status = format!("Learned note {}", formats::note_name(note));
+                    if let Some(pad) = self.templates[self.template_index].pad_for_note(note) {
+                        *self.params.pad_name.lock().unwrap() = pad.name.clone();
+                    }
+                    set_param(cx, &self.params.note, note as i32);
+                }
+                Learned::Cc { param, cc } => {
+                    self.learning = None;
+                    let param = &self.learnable_params[param];
+                    self.learn_status = format!("{} follows CC {cc}", param.name);
+                    let param_id = param.id.clone();
+                    // published so the audio thread applies the mapping while the editor is closed
+                    self.edit_settings(|params| {
+                        params.cc_mappings.lock().unwrap().insert(param_id, cc);
+                    });
+                }
+                Learned::CcMoved { cc, value } => {
+                    // the audio thread applies the CC already, the parameters are set through the
+                    // host like the sliders do so it sees and records the change
+                    let cc_mappings = self.params.cc_mappings.lock().unwrap();
+                    for param in &self.learnable_params {
+                        if cc_mappings.get(&param.id) == Some(&cc) {
+                            cx.emit(RawParamEvent::BeginSetParameter(param.ptr));
+                            cx.emit(RawParamEvent::SetParameterNormalized(param.ptr, value));
+                            cx.emit(RawParamEvent::EndSetParameter(param.ptr));
+                        }
+                    }
+                }
+            },
+            AppEvent::ClearCcMapping(param) => {
+                let param_id = self.learnable_params[*param].id.clone();
+                self.edit_settings(|params| {
+                    params.cc_mappings.lock().unwrap().remove(&param_id);
+                });
+            }
+            AppEvent::ToggleHiHat => self.edit_settings(|params| {
+                let mut hi_hat = params.hi_hat.lock().unwrap();
+                *hi_hat = match *hi_hat {
+                    Some(_) => None,
+                    None => Some(HiHat::default()),
+                };
+            }),
+            AppEvent::ToggleChokePressure => self.edit_settings(|params| {
+                let mut choke_pressure = params.choke_pressure.lock().unwrap();
+                *choke_pressure = match *choke_pressure {
+                    Some(_) => None,
+                    None => Some(DEFAULT_CHOKE_PRESSURE),
+                };
+            }),
+            AppEvent::StepChokePressure(steps) => self.edit_settings(|params| {
+                if let Some(choke_pressure) = &mut *params.choke_pressure.lock().unwrap() {
+                    *choke_pressure = (*choke_pressure + steps * 0.05).clamp(0.05, 1.0);
+                }
+            }),
+            AppEvent::CycleSampleReport => self.edit_settings(|params| {
+                let mut sample_report = params.sample_report.lock().unwrap();
+                *sample_report = sample_report.next();
+            }),
+            AppEvent::StepSampleReportChannel(steps) => self.edit_settings(|params| {
+                let mut sample_report = params.sample_report.lock().unwrap();
+                if let Some(channel) = sample_report.channel() {
+                    *sample_report =
+                        sample_report.with_channel((channel as i32 + steps).clamp(0, 15) as u8);
+                }
+            }),
+            AppEvent::CycleRouting => self.edit_settings(|params| {
+                let mut routing = params.routing.lock().unwrap();
+                *routing = next(&Routing::ALL, |policy| policy == &*routing);
+            }),
+            AppEvent::StepOutputTranspose(steps) => self.edit_settings(|params| {
+                let mut output_transpose = params.output_transpose.lock().unwrap();
+                *output_transpose = (*output_transpose as i32 + steps).clamp(-127, 127) as i8;
+            }),
+            AppEvent::ToggleMpe => self.edit_settings(|params| {
+                let mut mpe = params.mpe.lock().unwrap();
+                *mpe = match *mpe {
+                    Some(_) => None,
+                    None => Some(Mpe::default()),
+                };
+            }),
+            AppEvent::StepMpeBendRange(steps) => self.edit_settings(|params| {
+                if let Some(mpe) = &mut *params.mpe.lock().unwrap() {
+                    mpe.pitch_bend_range = (mpe.pitch_bend_range + steps).clamp(1.0, 96.0);
+                }
+            }),
+            AppEvent::AddModRoute => self.edit_settings(|params| {
+                params.mod_routes.lock().unwrap().push(ModRoute::default());
+            }),
+            AppEvent::RemoveModRoute(index) => self.edit_settings(|params| {
+                let mut mod_routes = params.mod_routes.lock().unwrap();
+                if *index < mod_routes.len() {
+                    mod_routes.remove(*index);
+                }
+            }),
+            AppEvent::CycleModSource(index) => self.edit_mod_route(*index, |route| {
+                route.source = next(&ModSource::ALL, |source| {
+                    std::mem::discriminant(source) == std::mem::discriminant(&route.source)
+                });
+            }),
+            AppEvent::CycleModDestination(index) => self.edit_mod_route(*index, |route| {
+                route.destination = next(&ModDestination::ALL, |destination| {
+                    *destination == route.destination
+                });
+                route.amount = 0.0;
+            }),
+            AppEvent::CycleModCurve(index) => self.edit_mod_route(*index, |route| {
+                route.curve = next(&Curve::ALL, |curve| *curve == route.curve);
+            }),
+            AppEvent::StepModCc(index, steps) => self.edit_mod_route(*index, |route| {
+                if let ModSource::Cc(cc) = &mut route.source {
+                    *cc = (*cc as i32 + steps).clamp(0, 127) as u8;
+                }
+            }),
+            AppEvent::StepModAmount(index, steps) => self.edit_mod_route(*index, |route| {
+                route.amount += steps * route.destination.step();
+            }),
+            AppEvent::PickBankKit => {
+                cx.spawn(|cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Kit", KIT_EXTENSIONS)
+                        .pick_file()
+                    {
+                        cx_proxy.emit(AppEvent::AddBankKit(path));
+                    }
+                });
+            }
+            AppEvent::AddBankKit(path) => {
+                self.params
+                    .kit_bank
+                    .lock()
+                    .unwrap()
+                    .push(library::to_portable(path));
+                self.preload_kit_bank();
+            }
+            AppEvent::RemoveBankKit(index) => {
+                {
+                    let mut kit_bank = self.params.kit_bank.lock().unwrap();
+                    if *index < kit_bank.len() {
+                        kit_bank.remove(*index);
+                    }
+                }
+                self.preload_kit_bank();
+            }
+            AppEvent::ApplyPad(pad) => {
+                self.debug = format!("loading pad: {}", pad.name);
+                *self.params.pad_name.lock().unwrap() = pad.name.clone();
+                set_param(cx, &self.params.note, pad.note as i32);
+                set_param(cx, &self.params.min_velocity, pad.min_velocity as i32);
+                set_param(cx, &self.params.max_velocity, pad.max_velocity as i32);
+                set_param(cx, &self.params.min_volume, pad.min_volume);
+                set_param(cx, &self.params.max_volume, pad.max_volume);
+                set_param(cx, &self.params.gain, pad.gain);
+                set_param(cx, &self.params.pan, pad.pan);
+                set_param(cx, &self.params.pitch, pad.pitch);
+                set_param(cx, &self.params.cutoff, pad.cutoff);
+
                 if let Err(e) = self
                     .producer
                     .lock()
                     .unwrap()
-                    .push(ThreadMessage::LoadSample(path.clone()))
+                    .push(ThreadMessage::LoadPad(pad.clone()))
                 {
                     self.debug = e.to_string();
                 }
             }
+            AppEvent::LoadSamples(paths) => {
+                self.debug = format!("loading: {paths:?}");
+                if let Err(e) = self
+                    .producer
+                    .lock()
+                    .unwrap()
+                    .push(ThreadMessage::LoadSamples(paths.clone()))
+                {
+                    self.debug = e.to_string();
+                    self.load_report = format!(
+                        "{} file(s) weren't loaded because the plugin is busy, try again",
+                        paths.len()
+                    );
+                }
+            }
+            AppEvent::SetLoadReport(report) => {
+                self.load_report = report.clone();
+            }
             AppEvent::RemoveSample(path) => {
                 self.debug = format!("removing: {path:?}");
                 if let Err(e) = self
@@ -63,8 +611,394 @@ impl Model for Data {
                     self.debug = e.to_string();
                 }
             }
+            AppEvent::SelectSample(path) => {
+                if self.selected_sample.as_ref() == Some(path) {
+                    self.selected_sample = None;
+                    self.waveform.select(None);
+                } else {
+                    self.selected_sample = Some(path.clone());
+                    self.selected_duration = sample_pool::file_duration(&library::resolve(path));
+                    self.waveform.select(Some(path.clone()));
+
+                    let waveform = self.waveform.clone();
+                    let path = path.clone();
+                    cx.spawn(move |_| waveform.load_peaks(&path));
+                }
+            }
+            AppEvent::StepZone(path, setting, steps) => {
+                let duration = self.selected_duration;
+                self.edit_zone(path, |zone| setting.step(zone, *steps, duration));
+            }
+            AppEvent::ToggleReverse(path) => {
+                self.edit_zone(path, |zone| zone.reverse = !zone.reverse);
+            }
+            AppEvent::Audition(path) => self.audition(path.clone()),
+            AppEvent::StepAuditionVelocity(steps) => {
+                self.audition_velocity =
+                    (self.audition_velocity as i32 + steps).clamp(1, 127) as u8;
+            }
+            AppEvent::PickBrowserFolder => {
+                cx.spawn(|cx_proxy| {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        cx_proxy.emit(AppEvent::BrowseTo(path));
+                    }
+                });
+            }
+            AppEvent::BrowseTo(path) => {
+                self.browser_dir = Some(path.clone());
+                self.browser_query.clear();
+                self.refresh_browser(cx);
+            }
+            AppEvent::BrowseUp => {
+                if let Some(parent) = self.browser_dir.as_ref().and_then(|dir| dir.parent()) {
+                    self.browser_dir = Some(parent.to_path_buf());
+                    self.browser_query.clear();
+                    self.refresh_browser(cx);
+                }
+            }
+            AppEvent::SearchBrowser(query) => {
+                self.browser_query = query.clone();
+                self.refresh_browser(cx);
+            }
+            AppEvent::SetBrowserEntries(entries) => {
+                self.browser_entries = entries.clone();
+            }
+            AppEvent::ToggleFavouriteFolder => {
+                if let Some(dir) = &self.browser_dir {
+                    library::toggle_favourite_folder(dir);
+                    self.favourite_folders = library::favourite_folders();
+                }
+            }
+            AppEvent::TogglePreviewOnHover => {
+                self.preview_on_hover = !self.preview_on_hover;
+            }
+            AppEvent::Preview(path) => {
+                let producer = self.producer.clone();
+                let sample_rate = self.waveform.sample_rate.load(Ordering::Relaxed);
+                let velocity = self.audition_velocity as f32 / 127.0;
+                let path = path.clone();
+                cx.spawn(move |_| match sample_pool::load(&path, sample_rate) {
+                    Ok((sample, _)) => {
+                        // built here so the audio thread doesn't allocate it
+                        let _ = producer.lock().unwrap().push(ThreadMessage::Preview {
+                            path: Arc::from(path),
+                            sample,
+                            velocity,
+                        });
+                    }
+                    Err(status) => nih_warn!("can't preview {path:?}: {}", status.label()),
+                });
+            }
+            AppEvent::HoverPreview(path) => {
+                if self.preview_on_hover {
+                    cx.emit(AppEvent::Preview(path.clone()));
+                }
+            }
+            AppEvent::AddPath(path) => {
+                if path.is_dir() {
+                    cx.emit(AppEvent::LoadFolder(path.clone()));
+                } else {
+                    cx.emit(AppEvent::LoadSamples(vec![path.clone()]));
+                }
+            }
+            AppEvent::OpenPath(path) => {
+                let extension = path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if path.is_dir() {
+                    cx.emit(AppEvent::AddPath(path.clone()));
+                } else if KIT_EXTENSIONS.contains(&extension.as_str()) {
+                    cx.emit(AppEvent::LoadKitFile(path.clone()));
+                } else if formats::IMPORT_EXTENSIONS.contains(&extension.as_str()) {
+                    cx.emit(AppEvent::ImportFile(path.clone()));
+                } else {
+                    cx.emit(AppEvent::AddPath(path.clone()));
+                }
+            }
+            AppEvent::RelinkMissingSamples => {
+                let sample_hashes = self.params.sample_hashes.lock().unwrap().clone();
+                let missing = self
+                    .sample_status
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|(_, status)| status.is_missing())
+                    .map(|(path, _)| (path.clone(), sample_hashes.get(path).copied()))
+                    .collect::<Vec<_>>();
+
+                if missing.is_empty() {
+                    self.debug = "no missing samples".into();
+                    return;
+                }
+
+                cx.spawn(move |cx_proxy| {
+                    if let Some(root) = rfd::FileDialog::new().pick_folder() {
+                        let relinks = relink::find_relinks(&root, &missing);
+                        cx_proxy.emit(AppEvent::RelinkSamples(relinks));
+                    }
+                });
+            }
+            AppEvent::RelinkSamples(relinks) => {
+                self.debug = format!("relinking {} sample(s)", relinks.len());
+                if let Err(e) = self
+                    .producer
+                    .lock()
+                    .unwrap()
+                    .push(ThreadMessage::RelinkSamples(relinks.clone()))
+                {
+                    self.debug = e.to_string();
+                }
+            }
+        });
+
+        event.map(|waveform_event, _| match waveform_event {
+            WaveformEvent::SetMarker(marker, position) => {
+                if let (Some(path), Some(duration)) =
+                    (self.selected_sample.clone(), self.waveform.duration())
+                {
+                    self.edit_zone(&path, |zone| marker.set(zone, *position, duration));
+                }
+            }
+            WaveformEvent::Audition => {
+                if let Some(path) = self.selected_sample.clone() {
+                    self.audition(path);
+                }
+            }
+        });
+    }
+}
+
+impl Data {
+    /// Changes settings the audio thread reads, and has them published to it
+    fn edit_settings(&mut self, edit: impl FnOnce(&NihSamplerParams)) {
+        edit(&self.params);
+        if let Err(e) = self
+            .producer
+            .lock()
+            .unwrap()
+            .push(ThreadMessage::UpdateSettings)
+        {
+            self.debug = e.to_string();
+        }
+    }
+
+    fn edit_mod_route(&mut self, index: usize, edit: impl FnOnce(&mut ModRoute)) {
+        self.edit_settings(|params| {
+            if let Some(route) = params.mod_routes.lock().unwrap().get_mut(index) {
+                edit(route);
+            }
+        });
+    }
+
+    /// Edits the zone of a sample in the sample list
+    fn edit_zone(&mut self, path: &PathBuf, edit: impl FnOnce(&mut Zone)) {
+        self.edit_settings(|params| {
+            let mut samples = params.samples.lock().unwrap();
+            if let Some(sample) = samples.iter_mut().find(|sample| &sample.path == path) {
+                edit(&mut sample.zone);
+            }
+        });
+    }
+
+    /// Lists the browser's folder, or searches it when there's a query, in the background
+    fn refresh_browser(&self, cx: &mut EventContext) {
+        let Some(dir) = self.browser_dir.clone() else {
+            return;
+        };
+        let query = self.browser_query.clone();
+        cx.spawn(move |cx_proxy| {
+            let entries = if query.trim().is_empty() {
+                browser::list(&dir)
+            } else {
+                browser::search(&dir, &query)
+            };
+            let _ = cx_proxy.emit(AppEvent::SetBrowserEntries(entries));
         });
     }
+
+    fn audition(&mut self, path: PathBuf) {
+        if let Err(e) = self.producer.lock().unwrap().push(ThreadMessage::Audition {
+            path: Arc::from(path),
+            velocity: self.audition_velocity as f32 / 127.0,
+        }) {
+            self.debug = e.to_string();
+        }
+    }
+
+    fn preload_kit_bank(&mut self) {
+        if let Err(e) = self
+            .producer
+            .lock()
+            .unwrap()
+            .push(ThreadMessage::PreloadKitBank)
+        {
+            self.debug = e.to_string();
+        }
+    }
+}
+
+/// The item after the one matching `is_current`, wrapping around
+fn next<T: Copy>(items: &[T], is_current: impl Fn(&T) -> bool) -> T {
+    let index = items
+        .iter()
+        .position(is_current)
+        .map_or(0, |index| index + 1);
+    items[index % items.len()]
+}
+
+/// A per-sample setting that can be changed from the sample list
+#[derive(Clone, Copy, PartialEq)]
+enum ZoneSetting {
+    Volume,
+    Tune,
+    Pan,
+    Weight,
+    MinVelocity,
+    MaxVelocity,
+    Start,
+    End,
+}
+
+impl ZoneSetting {
+    const ALL: [ZoneSetting; 8] = [
+        ZoneSetting::Volume,
+        ZoneSetting::Tune,
+        ZoneSetting::Pan,
+        ZoneSetting::Weight,
+        ZoneSetting::MinVelocity,
+        ZoneSetting::MaxVelocity,
+        ZoneSetting::Start,
+        ZoneSetting::End,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ZoneSetting::Volume => "Gain",
+            ZoneSetting::Tune => "Tune",
+            ZoneSetting::Pan => "Pan",
+            ZoneSetting::Weight => "Weight",
+            ZoneSetting::MinVelocity => "Vel low",
+            ZoneSetting::MaxVelocity => "Vel high",
+            ZoneSetting::Start => "Start",
+            ZoneSetting::End => "End",
+        }
+    }
+
+    fn describe(&self, zone: &Zone) -> String {
+        match self {
+            ZoneSetting::Volume => format!("{:+.1} dB", zone.volume),
+            ZoneSetting::Tune => format!("{:+.0} ct", zone.tune),
+            ZoneSetting::Pan => format!("{:+.0}", zone.pan),
+            ZoneSetting::Weight => format!("{:.2}", zone.weight),
+            ZoneSetting::MinVelocity => zone.min_velocity.to_string(),
+            ZoneSetting::MaxVelocity => zone.max_velocity.to_string(),
+            ZoneSetting::Start => format!("{:.3} s", zone.start),
+            ZoneSetting::End => zone.end.map_or("Full".into(), |end| format!("{end:.3} s")),
+        }
+    }
+
+    /// Steps are 1 dB, 10 cents, 10% of the pan, a quarter of the default weight, one velocity
+    /// and 10 ms. The end can only be moved in once the sample's length is known, and moving it
+    /// past the length plays the whole sample again.
+    fn step(&self, zone: &mut Zone, steps: f32, duration: Option<f32>) {
+        match self {
+            ZoneSetting::Volume => zone.volume = (zone.volume + steps).clamp(-60.0, 12.0),
+            ZoneSetting::Tune => zone.tune = (zone.tune + steps * 10.0).clamp(-2400.0, 2400.0),
+            ZoneSetting::Pan => zone.pan = (zone.pan + steps * 10.0).clamp(-100.0, 100.0),
+            ZoneSetting::Weight => zone.weight = (zone.weight + steps * 0.25).max(0.0),
+            ZoneSetting::MinVelocity => {
+                zone.min_velocity =
+                    (zone.min_velocity as f32 + steps).clamp(0.0, zone.max_velocity as f32) as u8;
+            }
+            ZoneSetting::MaxVelocity => {
+                zone.max_velocity =
+                    (zone.max_velocity as f32 + steps).clamp(zone.min_velocity as f32, 127.0) as u8;
+            }
+            ZoneSetting::Start => {
+                let max = zone.end.or(duration).unwrap_or(f32::MAX);
+                zone.start = (zone.start + steps * 0.01).clamp(0.0, max);
+            }
+            ZoneSetting::End => {
+                if let Some(end) = zone.end.or(duration) {
+                    let end = (end + steps * 0.01).max(zone.start);
+                    zone.end = duration
+                        .is_none_or(|duration| end < duration)
+                        .then_some(end);
+                }
+            }
+        }
+    }
+}
+
+/// Describes one of a sample's settings for the editor
+fn zone_label(path: PathBuf, setting: ZoneSetting) -> impl Lens<Target = String> {
+    Data::params.map(move |params| setting.describe(&params.sample_zone(&path)))
+}
+
+/// Controls for the settings of the sample at `path`, shown under it in the sample list
+fn sample_settings(cx: &mut Context, path: PathBuf) {
+    VStack::new(cx, |cx| {
+        for settings in ZoneSetting::ALL.chunks(4) {
+            HStack::new(cx, |cx| {
+                for &setting in settings {
+                    Label::new(cx, setting.name()).class("zone-setting");
+                    let step_path = path.clone();
+                    Button::new(
+                        cx,
+                        move |cx| cx.emit(AppEvent::StepZone(step_path.clone(), setting, -1.0)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("secondary-button");
+                    Label::new(cx, zone_label(path.clone(), setting)).class("zone-value");
+                    let step_path = path.clone();
+                    Button::new(
+                        cx,
+                        move |cx| cx.emit(AppEvent::StepZone(step_path.clone(), setting, 1.0)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("secondary-button");
+                }
+            })
+            .class("button-row")
+            .class("template-row");
+        }
+
+        HStack::new(cx, |cx| {
+            let reverse_path = path.clone();
+            Checkbox::new(
+                cx,
+                Data::params.map(move |params| params.sample_zone(&reverse_path).reverse),
+            )
+            .on_toggle(move |cx| cx.emit(AppEvent::ToggleReverse(path.clone())));
+            Label::new(cx, "Reverse");
+        })
+        .class("checkbox-row");
+    })
+    .class("sample-settings");
+}
+
+/// Describes part of a modulation route for the editor
+fn mod_route_label(
+    index: usize,
+    describe: impl Fn(&ModRoute) -> String + 'static,
+) -> impl Lens<Target = String> {
+    Data::params.map(move |params| {
+        params
+            .mod_routes
+            .lock()
+            .unwrap()
+            .get(index)
+            .map(&describe)
+            .unwrap_or_default()
+    })
+}
+
+/// Sets a parameter as if the user changed it, so the host picks up the change
+fn set_param<P: Param>(cx: &mut EventContext, param: &P, value: P::Plain) {
+    cx.emit(ParamEvent::BeginSetParameter(param).upcast());
+    cx.emit(ParamEvent::SetParameter(param, value).upcast());
+    cx.emit(ParamEvent::EndSetParameter(param).upcast());
 }
 
 pub fn default_state() -> Arc<ViziaState> {
@@ -76,6 +1010,9 @@ pub fn create(
     editor_state: Arc<ViziaState>,
     producer: Arc<Mutex<rtrb::Producer<ThreadMessage>>>,
     visualizer: Arc<VisualizerData>,
+    waveform: Arc<WaveformData>,
+    sample_status: Arc<Mutex<HashMap<PathBuf, SampleStatus>>>,
+    learn_consumer: Arc<Mutex<rtrb::Consumer<Learned>>>,
 ) -> Option<Box<dyn Editor>> {
     create_vizia_editor(editor_state, ViziaTheming::Custom, move |cx, _| {
         cx.add_theme(include_str!("theme.css"));
@@ -86,54 +1023,739 @@ pub fn create(
             producer: producer.clone(),
             debug: "nothing".into(),
             visualizer: visualizer.clone(),
+            waveform: waveform.clone(),
+            sample_status: sample_status.clone(),
+            remember_folders: true,
+            import_report: String::new(),
+            load_report: String::new(),
+            templates: templates::all(),
+            template_index: 0,
+            template_name: Template::general_midi().name,
+            learnable_params: learnable_params(params.as_ref()),
+            learning: None,
+            learn_status: String::new(),
+            selected_sample: None,
+            selected_duration: None,
+            audition_velocity: DEFAULT_AUDITION_VELOCITY,
+            browser_dir: None,
+            browser_query: String::new(),
+            browser_entries: vec![],
+            favourite_folders: library::favourite_folders(),
+            preview_on_hover: false,
         }
         .build(cx);
 
+        // forwards the notes and CCs captured by the audio thread for as long as the editor is open
+        let learn_consumer = learn_consumer.clone();
+        cx.spawn(move |cx_proxy| loop {
+            while let Ok(learned) = learn_consumer.lock().unwrap().pop() {
+                if cx_proxy.emit(AppEvent::Learned(learned)).is_err() {
+                    return;
+                }
+            }
+            if learn_consumer.lock().unwrap().is_abandoned() {
+                return;
+            }
+            std::thread::sleep(LEARN_POLL_INTERVAL);
+        });
+
         ResizeHandle::new(cx);
         VStack::new(cx, |cx| {
             HStack::new(cx, |cx| {
                 Label::new(cx, "Nih Sampler").id("logo");
                 Visualizer::new(cx, Data::visualizer).id("visualizer");
+                Waveform::new(cx, Data::waveform, Data::params).id("waveform");
             })
             .class("top-bar");
 
             VStack::new(cx, |cx| {
                 // Label::new(cx, Data::debug).overflow(Overflow::Hidden);
-                Label::new(cx, "Settings").class("heading");
-                GenericUi::new(cx, Data::params).id("settings-container");
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Settings").class("heading");
+
+                    HStack::new(cx, |cx| {
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::LoadKit),
+                            |cx| Label::new(cx, "Load Kit"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::SaveKit),
+                            |cx| Label::new(cx, "Save Kit"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::Import),
+                            |cx| Label::new(cx, "Import"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::ExportSfz),
+                            |cx| Label::new(cx, "Export SFZ"),
+                        )
+                        .class("secondary-button");
+                    })
+                    .class("button-row");
+                })
+                .height(Auto)
+                .col_between(Stretch(1.0));
 
                 HStack::new(cx, |cx| {
-                    Label::new(cx, "Samples").class("heading");
+                    Label::new(cx, "Template");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::CycleTemplate(-1)),
+                        |cx| Label::new(cx, "<"),
+                    )
+                    .class("secondary-button");
+                    Label::new(cx, Data::template_name).class("template-name");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::CycleTemplate(1)),
+                        |cx| Label::new(cx, ">"),
+                    )
+                    .class("secondary-button");
 
+                    Label::new(cx, "Pad");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::CyclePad(-1)),
+                        |cx| Label::new(cx, "<"),
+                    )
+                    .class("secondary-button");
+                    Label::new(
+                        cx,
+                        Data::params.map(|params| params.pad_name.lock().unwrap().clone()),
+                    )
+                    .class("template-name");
                     Button::new(
                         cx,
-                        |cx| cx.emit(AppEvent::OpenFilePicker),
-                        |cx| Label::new(cx, "Add Sample(s)"),
+                        |cx| cx.emit(AppEvent::CyclePad(1)),
+                        |cx| Label::new(cx, ">"),
                     )
-                    .id("add-sample-button");
+                    .class("secondary-button");
+
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::NewKit),
+                        |cx| Label::new(cx, "New Kit"),
+                    )
+                    .class("secondary-button");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::RemapKit),
+                        |cx| Label::new(cx, "Remap Kit"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("button-row")
+                .class("template-row");
+
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::ToggleLearn(LearnTarget::Note)),
+                        |cx| Label::new(cx, "Learn Note"),
+                    )
+                    .class("secondary-button");
+                    Label::new(cx, Data::learn_status).class("learn-status");
+                })
+                .class("button-row")
+                .class("template-row");
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(
+                        cx,
+                        Data::params.map(|params| params.hi_hat.lock().unwrap().is_some()),
+                    )
+                    .on_toggle(|cx| cx.emit(AppEvent::ToggleHiHat));
+                    Label::new(
+                        cx,
+                        Data::params.map(|params| match *params.hi_hat.lock().unwrap() {
+                            Some(hi_hat) => format!(
+                                "Hi-hat: samples follow the pedal on CC {}, closing it below {} \
+                                 chokes open hats",
+                                hi_hat.cc, hi_hat.close_threshold
+                            ),
+                            None => "Hi-hat".into(),
+                        }),
+                    );
+                })
+                .class("checkbox-row");
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(
+                        cx,
+                        Data::params.map(|params| params.choke_pressure.lock().unwrap().is_some()),
+                    )
+                    .on_toggle(|cx| cx.emit(AppEvent::ToggleChokePressure));
+                    Label::new(
+                        cx,
+                        Data::params.map(|params| match *params.choke_pressure.lock().unwrap() {
+                            Some(choke_pressure) => format!(
+                                "Choke when poly aftertouch reaches {:.0}%",
+                                choke_pressure * 100.0
+                            ),
+                            None => "Choke by poly aftertouch".into(),
+                        }),
+                    );
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepChokePressure(-1.0)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("secondary-button");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepChokePressure(1.0)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("checkbox-row");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Report picked sample");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::CycleSampleReport),
+                        |cx| {
+                            Label::new(
+                                cx,
+                                Data::params
+                                    .map(|params| params.sample_report.lock().unwrap().describe()),
+                            )
+                        },
+                    )
+                    .class("secondary-button");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepSampleReportChannel(-1)),
+                        |cx| Label::new(cx, "Ch-"),
+                    )
+                    .class("secondary-button");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepSampleReportChannel(1)),
+                        |cx| Label::new(cx, "Ch+"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("button-row")
+                .class("template-row");
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(
+                        cx,
+                        Data::params.map(|params| params.mpe.lock().unwrap().is_some()),
+                    )
+                    .on_toggle(|cx| cx.emit(AppEvent::ToggleMpe));
+                    Label::new(
+                        cx,
+                        Data::params.map(|params| match *params.mpe.lock().unwrap() {
+                            Some(mpe) => format!(
+                                "MPE: per-note pitch bend (±{} st), pressure and timbre",
+                                mpe.pitch_bend_range
+                            ),
+                            None => "MPE".into(),
+                        }),
+                    );
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepMpeBendRange(-1.0)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("secondary-button");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepMpeBendRange(1.0)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("checkbox-row");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "MIDI output");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::CycleRouting),
+                        |cx| {
+                            Label::new(
+                                cx,
+                                Data::params.map(|params| {
+                                    params.routing.lock().unwrap().name().to_string()
+                                }),
+                            )
+                        },
+                    )
+                    .class("secondary-button");
+                    Label::new(cx, "Transpose");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepOutputTranspose(-1)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("secondary-button");
+                    Label::new(
+                        cx,
+                        Data::params
+                            .map(|params| format!("{:+}", params.output_transpose.lock().unwrap())),
+                    )
+                    .class("cc-mapping");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepOutputTranspose(1)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("button-row")
+                .class("template-row");
+
+                VStack::new(cx, |cx| {
+                    for (index, param) in learnable_params(params.as_ref()).into_iter().enumerate() {
+                        HStack::new(cx, |cx| {
+                            Label::new(cx, &param.name).class("learn-param");
+                            let param_id = param.id;
+                            Label::new(
+                                cx,
+                                Data::params.map(move |params| {
+                                    match params.cc_mappings.lock().unwrap().get(&param_id) {
+                                        Some(cc) => format!("CC {cc}"),
+                                        None => "-".into(),
+                                    }
+                                }),
+                            )
+                            .class("cc-mapping");
+                            Button::new(
+                                cx,
+                                move |cx| {
+                                    cx.emit(AppEvent::ToggleLearn(LearnTarget::Param(index)))
+                                },
+                                |cx| Label::new(cx, "Learn CC"),
+                            )
+                            .class("secondary-button");
+                            Label::new(cx, "Clear")
+                                .class("remove-label")
+                                .on_press(move |cx| cx.emit(AppEvent::ClearCcMapping(index)));
+                        })
+                        .class("button-row")
+                        .class("template-row");
+                    }
+                })
+                .class("learn-list");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Modulation").class("heading");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::AddModRoute),
+                        |cx| Label::new(cx, "Add Route"),
+                    )
+                    .class("secondary-button");
+                })
+                .height(Auto)
+                .col_between(Stretch(1.0));
+
+                List::new(
+                    cx,
+                    Data::params.map(|params| {
+                        (0..params.mod_routes.lock().unwrap().len()).collect::<Vec<_>>()
+                    }),
+                    |cx, index, _| {
+                        HStack::new(cx, |cx| {
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::CycleModSource(index)),
+                                move |cx| {
+                                    Label::new(
+                                        cx,
+                                        mod_route_label(index, |route| route.source.name()),
+                                    )
+                                },
+                            )
+                            .class("secondary-button");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::StepModCc(index, -1)),
+                                |cx| Label::new(cx, "CC-"),
+                            )
+                            .class("secondary-button");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::StepModCc(index, 1)),
+                                |cx| Label::new(cx, "CC+"),
+                            )
+                            .class("secondary-button");
+                            Label::new(cx, "to");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::CycleModDestination(index)),
+                                move |cx| {
+                                    Label::new(
+                                        cx,
+                                        mod_route_label(index, |route| {
+                                            route.destination.name().to_string()
+                                        }),
+                                    )
+                                },
+                            )
+                            .class("secondary-button");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::StepModAmount(index, -1.0)),
+                                |cx| Label::new(cx, "-"),
+                            )
+                            .class("secondary-button");
+                            Label::new(
+                                cx,
+                                mod_route_label(index, |route| {
+                                    format!("{:+.3}{}", route.amount, route.destination.unit())
+                                }),
+                            )
+                            .class("mod-amount");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::StepModAmount(index, 1.0)),
+                                |cx| Label::new(cx, "+"),
+                            )
+                            .class("secondary-button");
+                            Button::new(
+                                cx,
+                                move |cx| cx.emit(AppEvent::CycleModCurve(index)),
+                                move |cx| {
+                                    Label::new(
+                                        cx,
+                                        mod_route_label(index, |route| {
+                                            route.curve.name().to_string()
+                                        }),
+                                    )
+                                },
+                            )
+                            .class("secondary-button");
+                            Label::new(cx, "Remove")
+                                .class("remove-label")
+                                .on_press(move |cx| cx.emit(AppEvent::RemoveModRoute(index)));
+                        })
+                        .class("button-row")
+                        .class("template-row");
+                    },
+                )
+                .class("learn-list");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Kit Bank").class("heading");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::PickBankKit),
+                        |cx| Label::new(cx, "Add Kit"),
+                    )
+                    .class("secondary-button");
+                })
+                .height(Auto)
+                .col_between(Stretch(1.0));
+
+                List::new(
+                    cx,
+                    Data::params.map(|params| params.kit_bank.lock().unwrap().clone()),
+                    |cx, index, item| {
+                        let path = item.get(cx);
+                        HStack::new(cx, |cx| {
+                            Label::new(
+                                cx,
+                                &format!(
+                                    "Program {}: {}",
+                                    index + 1,
+                                    path.file_stem().unwrap_or_default().to_string_lossy()
+                                ),
+                            );
+                            Label::new(cx, "Remove")
+                                .class("remove-label")
+                                .on_press(move |cx| cx.emit(AppEvent::RemoveBankKit(index)));
+                        })
+                        .class("button-row")
+                        .class("template-row");
+                    },
+                )
+                .class("learn-list");
+
+                Label::new(cx, Data::import_report)
+                    .class("import-report")
+                    .overflow(Overflow::Hidden);
+                GenericUi::new(cx, Data::params).id("settings-container");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Browser").class("heading");
+
+                    HStack::new(cx, |cx| {
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::BrowseUp),
+                            |cx| Label::new(cx, "Up"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::ToggleFavouriteFolder),
+                            |cx| Label::new(cx, "Favourite"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::PickBrowserFolder),
+                            |cx| Label::new(cx, "Open Folder"),
+                        )
+                        .class("secondary-button");
+                    })
+                    .class("button-row");
+                })
+                .height(Auto)
+                .col_between(Stretch(1.0));
+
+                List::new(cx, Data::favourite_folders, |cx, _, item| {
+                    let folder = item.get(cx);
+                    Label::new(
+                        cx,
+                        &format!(
+                            "Favourite: {}",
+                            folder.file_name().unwrap_or_default().to_string_lossy()
+                        ),
+                    )
+                    .class("folder")
+                    .class("sample-name")
+                    .on_press(move |cx| cx.emit(AppEvent::BrowseTo(folder.clone())));
+                })
+                .class("learn-list");
+
+                HStack::new(cx, |cx| {
+                    Textbox::new(cx, Data::browser_query)
+                        .on_submit(|cx, query, _| cx.emit(AppEvent::SearchBrowser(query)))
+                        .class("browser-search");
+                    Label::new(
+                        cx,
+                        Data::browser_dir.map(|dir| {
+                            dir.as_ref().map_or("No folder open".into(), |dir| {
+                                dir.to_string_lossy().to_string()
+                            })
+                        }),
+                    )
+                    .overflow(Overflow::Hidden);
+                })
+                .class("checkbox-row");
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(cx, Data::preview_on_hover)
+                        .on_toggle(|cx| cx.emit(AppEvent::TogglePreviewOnHover));
+                    Label::new(
+                        cx,
+                        "Preview on hover, click a file to preview it or drag it into the sample list",
+                    );
+                })
+                .class("checkbox-row");
+
+                ScrollView::new(cx, 0.0, 0.0, false, true, |cx| {
+                    List::new(cx, Data::browser_entries, |cx, _, item| {
+                        let path = item.get(cx);
+                        let name = path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string();
+                        let is_dir = path.is_dir();
+                        HStack::new(cx, |cx| {
+                            let press_path = path.clone();
+                            let hover_path = path.clone();
+                            let name = Label::new(cx, &name).class("sample-name");
+                            if is_dir {
+                                name.on_press(move |cx| {
+                                    cx.emit(AppEvent::BrowseTo(press_path.clone()))
+                                });
+                            } else {
+                                name.on_press(move |cx| {
+                                    cx.emit(AppEvent::Preview(press_path.clone()))
+                                })
+                                .on_hover(move |cx| {
+                                    cx.emit(AppEvent::HoverPreview(hover_path.clone()))
+                                });
+                            }
+                            let add_path = path.clone();
+                            Label::new(cx, "Add")
+                                .class("play-label")
+                                .on_press(move |cx| cx.emit(AppEvent::AddPath(add_path.clone())));
+                        })
+                        .class("sample")
+                        .toggle_class("folder", is_dir)
+                        .on_drag(move |cx| cx.set_drop_data(DropData::File(path.clone())));
+                    })
+                    .class("vert-list")
+                    .class("sample-list");
+                })
+                .class("sample-scrollview")
+                .class("browser-scrollview");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Samples").class("heading");
+
+                    HStack::new(cx, |cx| {
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::RelinkMissingSamples),
+                            |cx| Label::new(cx, "Relink Missing"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::OpenFolderPicker),
+                            |cx| Label::new(cx, "Add Folder"),
+                        )
+                        .class("secondary-button");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(AppEvent::OpenFilePicker),
+                            |cx| Label::new(cx, "Add Sample(s)"),
+                        )
+                        .id("add-sample-button");
+                    })
+                    .class("button-row");
                 })
                 .height(Auto)
                 .col_between(Stretch(1.0));
 
+                Label::new(cx, Data::load_report)
+                    .class("import-report")
+                    .overflow(Overflow::Hidden)
+                    .on_press(|cx| cx.emit(AppEvent::SetLoadReport(String::new())));
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(cx, Data::remember_folders)
+                        .on_toggle(|cx| cx.emit(AppEvent::ToggleRememberFolders));
+                    Label::new(cx, "Remember folders and pick up new files on reload");
+                })
+                .class("checkbox-row");
+
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "Audition velocity");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepAuditionVelocity(-1)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("secondary-button");
+                    Label::new(
+                        cx,
+                        Data::audition_velocity.map(|velocity| velocity.to_string()),
+                    );
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(AppEvent::StepAuditionVelocity(1)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("secondary-button");
+                })
+                .class("button-row")
+                .class("template-row");
+
+                Label::new(
+                    cx,
+                    Data::params.map(|params| {
+                        match embed::total_size(&params.embedded_samples.lock().unwrap()) {
+                            0 => String::new(),
+                            size if size > embed::SIZE_WARNING_BYTES => format!(
+                                "Embedded: {} (large states may slow down saving)",
+                                embed::format_size(size)
+                            ),
+                            size => format!("Embedded: {}", embed::format_size(size)),
+                        }
+                    }),
+                )
+                .class("embed-size")
+                .toggle_class(
+                    "warning",
+                    Data::params.map(|params| {
+                        embed::total_size(&params.embedded_samples.lock().unwrap())
+                            > embed::SIZE_WARNING_BYTES
+                    }),
+                );
+
                 ScrollView::new(cx, 0.0, 0.0, false, true, |cx| {
                     List::new(
                         cx,
-                        Data::params.map(|params| params.sample_list.lock().unwrap().clone()),
-                        |cx, index, item| {
+                        Data::params.map(|params| params.sample_folders.lock().unwrap().clone()),
+                        |cx, _, item| {
                             HStack::new(cx, |cx| {
                                 Label::new(
                                     cx,
-                                    &item
-                                        .get(cx)
-                                        .file_name()
-                                        .unwrap_or_default()
-                                        .to_string_lossy()
-                                        .to_string(),
+                                    &format!("Folder: {}", item.get(cx).to_string_lossy()),
                                 );
-                                Label::new(cx, "Remove").class("remove-label").on_press(
-                                    move |cx| cx.emit(AppEvent::RemoveSample(item.get(cx).clone())),
+                                Label::new(cx, "Forget").class("remove-label").on_press(
+                                    move |cx| cx.emit(AppEvent::ForgetFolder(item.get(cx).clone())),
                                 );
                             })
+                            .class("sample")
+                            .class("folder");
+                        },
+                    )
+                    .class("vert-list")
+                    .class("sample-list");
+
+                    List::new(
+                        cx,
+                        Data::params.map(|params| params.sample_paths()),
+                        |cx, index, item| {
+                            let path = item.get(cx);
+                            VStack::new(cx, |cx| {
+                                HStack::new(cx, |cx| {
+                                    let select_path = path.clone();
+                                    Label::new(
+                                        cx,
+                                        &path
+                                            .file_name()
+                                            .unwrap_or_default()
+                                            .to_string_lossy()
+                                            .to_string(),
+                                    )
+                                    .class("sample-name")
+                                    .on_press(move |cx| {
+                                        cx.emit(AppEvent::SelectSample(select_path.clone()))
+                                    });
+                                    let status_path = path.clone();
+                                    Label::new(
+                                        cx,
+                                        Data::sample_status.map(move |sample_status| {
+                                            sample_status
+                                                .lock()
+                                                .unwrap()
+                                                .get(&status_path)
+                                                .map(|status| status.label())
+                                                .unwrap_or_default()
+                                                .to_string()
+                                        }),
+                                    )
+                                    .class("sample-status");
+                                    let play_path = path.clone();
+                                    Label::new(cx, "Play").class("play-label").on_press(
+                                        move |cx| cx.emit(AppEvent::Audition(play_path.clone())),
+                                    );
+                                    Label::new(cx, "Remove").class("remove-label").on_press(
+                                        move |cx| {
+                                            cx.emit(AppEvent::RemoveSample(item.get(cx).clone()))
+                                        },
+                                    );
+                                })
+                                .height(Auto)
+                                .col_between(Stretch(1.0));
+
+                                Binding::new(cx, Data::selected_sample, move |cx, selected| {
+                                    if selected.get(cx).as_ref() == Some(&path) {
+                                        sample_settings(cx, path.clone());
+                                    }
+                                });
+                            })
                             .class("sample");
                         },
                     )
@@ -145,7 +1767,13 @@ pub fn create(
             .class("main-body")
             .class("vert-list");
         })
-        .id("container");
+        .id("container")
+        // files and folders can be dropped anywhere, both from the browser and the file manager
+        .on_drop(|cx, data| {
+            if let DropData::File(path) = data {
+                cx.emit(AppEvent::OpenPath(path));
+            }
+        });
     })
 }
 fn param_row<L, Params, P, FMap>(cx: &mut Context, label: &str, params: L, params_to_param: FMap)
@@ -0,0 +1,395 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use nih_plug::prelude::AtomicF32;
+use nih_plug_vizia::vizia::{prelude::*, vg};
+
+use crate::{kit::Zone, sample_loader::SampleMap, NihSamplerParams};
+
+/// Number of min/max pairs computed for a whole sample
+const PEAK_BUCKETS: usize = 8192;
+
+/// Maximum number of playheads shown at once
+const MAX_PLAYHEADS: usize = 16;
+
+const MAX_ZOOM: f32 = 64.0;
+
+/// How close to a marker, in pixels, a click has to be to grab it
+const MARKER_GRAB_DISTANCE: f32 = 6.0;
+
+/// Min/max overview of a sample, computed in the background when it gets selected
+pub struct Peaks {
+    pub path: PathBuf,
+    /// Length of the sample in seconds
+    pub duration: f32,
+    /// Lowest and highest value of all channels in each bucket
+    pub buckets: Vec<(f32, f32)>,
+}
+
+/// Shared between the audio thread and the editor: the sample shown in the waveform view, the
+/// positions of its voices and its peaks
+pub struct WaveformData {
+    /// Rate of the instance, which is what the samples are decoded at
+    pub sample_rate: AtomicF32,
+    /// The instance's samples, shared with the sample loader
+    samples: Arc<Mutex<Arc<SampleMap>>>,
+    selected: Mutex<Option<PathBuf>>,
+    /// Positions of the selected sample's voices in seconds, negative for unused slots
+    playheads: [AtomicF32; MAX_PLAYHEADS],
+    peaks: Mutex<Option<Peaks>>,
+}
+
+impl WaveformData {
+    pub fn new(samples: Arc<Mutex<Arc<SampleMap>>>) -> Self {
+        Self {
+            sample_rate: AtomicF32::new(44100.0),
+            samples,
+            selected: Mutex::new(None),
+            playheads: std::array::from_fn(|_| AtomicF32::new(-1.0)),
+            peaks: Mutex::new(None),
+        }
+    }
+
+    pub fn select(&self, path: Option<PathBuf>) {
+        *self.selected.lock().unwrap() = path;
+        *self.peaks.lock().unwrap() = None;
+        for playhead in &self.playheads {
+            playhead.store(-1.0, Ordering::Relaxed);
+        }
+    }
+
+    /// Stores the positions of the voices playing the selected sample, given as paths and
+    /// positions in seconds. Skipped while the editor changes the selection.
+    pub fn store_playheads<'a>(&self, voices: impl Iterator<Item = (&'a Path, f32)>) {
+        let Ok(selected) = self.selected.try_lock() else {
+            return;
+        };
+        let mut positions = voices
+            .filter(|(path, _)| selected.as_deref() == Some(*path))
+            .map(|(_, position)| position);
+        for playhead in &self.playheads {
+            playhead.store(positions.next().unwrap_or(-1.0), Ordering::Relaxed);
+        }
+    }
+
+    /// Computes the peaks of the selected sample from the instance's decoded data, so samples
+    /// that are only embedded in the plugin state are shown as well. Runs on a background thread.
+    pub fn load_peaks(&self, path: &Path) {
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        let Some(sample) = self.samples.lock().unwrap().get(path).cloned() else {
+            return;
+        };
+
+        let bucket_size = sample.len().div_ceil(PEAK_BUCKETS).max(1);
+        let buckets = (0..sample.len())
+            .step_by(bucket_size)
+            .map(|bucket_start| {
+                let range = bucket_start..(bucket_start + bucket_size).min(sample.len());
+                sample
+                    .0
+                    .iter()
+                    .flat_map(|channel| &channel[range.clone()])
+                    .fold((0.0f32, 0.0f32), |(min, max), value| {
+                        (min.min(*value), max.max(*value))
+                    })
+            })
+            .collect();
+
+        // the selection may have changed while the sample was being read
+        if self.selected.lock().unwrap().as_deref() == Some(path) {
+            *self.peaks.lock().unwrap() = Some(Peaks {
+                path: path.to_path_buf(),
+                duration: sample.len() as f32 / sample_rate,
+                buckets,
+            });
+        }
+    }
+
+    /// Length of the selected sample in seconds, once its peaks are computed
+    pub fn duration(&self) -> Option<f32> {
+        self.peaks
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|peaks| peaks.duration)
+    }
+}
+
+/// A marker on the waveform that can be dragged to change the sample's zone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Marker {
+    Start,
+    End,
+    LoopStart,
+    LoopEnd,
+}
+
+impl Marker {
+    const ALL: [Marker; 4] = [
+        Marker::Start,
+        Marker::End,
+        Marker::LoopStart,
+        Marker::LoopEnd,
+    ];
+
+    /// Position in seconds. The loop markers of samples without a loop sit at the start and end.
+    pub fn position(&self, zone: &Zone, duration: f32) -> f32 {
+        let end = zone.end.unwrap_or(duration);
+        match self {
+            Marker::Start => zone.start,
+            Marker::End => end,
+            Marker::LoopStart => zone.loop_end.and(zone.loop_start).unwrap_or(zone.start),
+            Marker::LoopEnd => zone.loop_start.and(zone.loop_end).unwrap_or(end),
+        }
+    }
+
+    /// Moves the marker, keeping the start before the end and the loop inside of them. Moving the
+    /// end to the end of the sample plays the whole sample again, and moving a loop marker of a
+    /// sample without a loop creates one.
+    pub fn set(&self, zone: &mut Zone, position: f32, duration: f32) {
+        let end = zone.end.unwrap_or(duration);
+        match self {
+            Marker::Start => zone.start = position.clamp(0.0, end),
+            Marker::End => {
+                let end = position.clamp(zone.start, duration);
+                zone.end = (end < duration).then_some(end);
+            }
+            Marker::LoopStart => {
+                let loop_end = zone.loop_end.unwrap_or(end);
+                zone.loop_start = Some(position.clamp(zone.start, loop_end));
+                zone.loop_end = Some(loop_end);
+            }
+            Marker::LoopEnd => {
+                let loop_start = zone.loop_start.unwrap_or(zone.start);
+                zone.loop_end = Some(position.clamp(loop_start, end));
+                zone.loop_start = Some(loop_start);
+            }
+        }
+    }
+
+    fn is_loop(&self) -> bool {
+        matches!(self, Marker::LoopStart | Marker::LoopEnd)
+    }
+
+    fn color(&self) -> vg::Color {
+        match self {
+            Marker::Start | Marker::End => vg::Color::rgb(255, 204, 48),
+            Marker::LoopStart | Marker::LoopEnd => vg::Color::rgb(154, 39, 237),
+        }
+    }
+}
+
+/// Emitted by the waveform view when a marker of the selected sample is dragged, or when the
+/// waveform is clicked anywhere else to audition the sample
+pub enum WaveformEvent {
+    SetMarker(Marker, f32),
+    Audition,
+}
+
+/// Waveform of the selected sample with its start, end and loop markers and the playheads of its
+/// voices. The loop markers are drawn and grabbed in the lower half of the view, so they can be
+/// told apart from the start and end when they're on top of them. Scrolling zooms in around the
+/// cursor, shift-scrolling moves the view and double clicking zooms out again. Clicking next to
+/// the markers plays the sample.
+pub struct Waveform {
+    waveform: Arc<WaveformData>,
+    params: Arc<NihSamplerParams>,
+    /// How many times the visible part is shorter than the sample
+    zoom: f32,
+    /// Start of the visible part as a fraction of the sample
+    scroll: f32,
+    dragging: Option<Marker>,
+}
+
+impl Waveform {
+    pub fn new<LW, LP>(cx: &mut Context, waveform: LW, params: LP) -> Handle<Self>
+    where
+        LW: Lens<Target = Arc<WaveformData>>,
+        LP: Lens<Target = Arc<NihSamplerParams>>,
+    {
+        Self {
+            waveform: waveform.get(cx),
+            params: params.get(cx),
+            zoom: 1.0,
+            scroll: 0.0,
+            dragging: None,
+        }
+        .build(cx, |_cx| {})
+    }
+
+    /// The selected sample's zone, or the default if it has none
+    fn zone(&self, path: &Path) -> Zone {
+        self.params.sample_zone(path)
+    }
+
+    /// Converts an x coordinate within the bounds to a position in seconds
+    fn position_at(&self, x: f32, bounds: BoundingBox, duration: f32) -> f32 {
+        let fraction = self.scroll + (x - bounds.x) / bounds.w / self.zoom;
+        fraction.clamp(0.0, 1.0) * duration
+    }
+
+    fn x_at(&self, position: f32, bounds: BoundingBox, duration: f32) -> f32 {
+        bounds.x + (position / duration - self.scroll) * self.zoom * bounds.w
+    }
+
+    fn clamp_scroll(&mut self) {
+        self.scroll = self.scroll.clamp(0.0, 1.0 - 1.0 / self.zoom);
+    }
+}
+
+impl View for Waveform {
+    fn element(&self) -> Option<&'static str> {
+        Some("waveform")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, meta| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                let Some(path) = self.waveform.selected.lock().unwrap().clone() else {
+                    return;
+                };
+                let Some(duration) = self.waveform.duration() else {
+                    return;
+                };
+                let bounds = cx.bounds();
+                let cursor_x = cx.mouse().cursorx;
+                let in_lower_half = cx.mouse().cursory > bounds.y + bounds.h / 2.0;
+                let zone = self.zone(&path);
+                self.dragging = Marker::ALL
+                    .iter()
+                    .map(|marker| {
+                        let x = self.x_at(marker.position(&zone, duration), bounds, duration);
+                        (*marker, (x - cursor_x).abs())
+                    })
+                    .filter(|(_, distance)| *distance <= MARKER_GRAB_DISTANCE)
+                    .min_by(|(a_marker, a), (b_marker, b)| {
+                        let preferred = |marker: &Marker| marker.is_loop() == in_lower_half;
+                        a.total_cmp(b)
+                            .then_with(|| preferred(b_marker).cmp(&preferred(a_marker)))
+                    })
+                    .map(|(marker, _)| marker);
+                if self.dragging.is_some() {
+                    cx.capture();
+                } else {
+                    cx.emit(WaveformEvent::Audition);
+                }
+                meta.consume();
+            }
+            WindowEvent::MouseMove(x, _) => {
+                if let (Some(marker), Some(duration)) = (self.dragging, self.waveform.duration()) {
+                    let position = self.position_at(*x, cx.bounds(), duration);
+                    cx.emit(WaveformEvent::SetMarker(marker, position));
+                    cx.needs_redraw();
+                }
+            }
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if self.dragging.take().is_some() {
+                    cx.release();
+                    meta.consume();
+                }
+            }
+            WindowEvent::MouseDoubleClick(MouseButton::Left) => {
+                self.zoom = 1.0;
+                self.scroll = 0.0;
+                cx.needs_redraw();
+            }
+            WindowEvent::MouseScroll(_, y) => {
+                let bounds = cx.bounds();
+                if cx.modifiers().shift() {
+                    self.scroll -= y * 0.1 / self.zoom;
+                } else {
+                    let cursor = ((cx.mouse().cursorx - bounds.x) / bounds.w).clamp(0.0, 1.0);
+                    let anchor = self.scroll + cursor / self.zoom;
+                    self.zoom = (self.zoom * 1.25f32.powf(*y)).clamp(1.0, MAX_ZOOM);
+                    self.scroll = anchor - cursor / self.zoom;
+                }
+                self.clamp_scroll();
+                cx.needs_redraw();
+                meta.consume();
+            }
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w == 0.0 || bounds.h == 0.0 {
+            return;
+        }
+
+        let peaks = self.waveform.peaks.lock().unwrap();
+        let Some(peaks) = peaks.as_ref().filter(|peaks| !peaks.buckets.is_empty()) else {
+            return;
+        };
+        let zone = self.zone(&peaks.path);
+
+        let line_width = cx.style.dpi_factor as f32;
+        let paint = vg::Paint::color(cx.font_color().cloned().unwrap_or_default().into())
+            .with_line_width(line_width);
+        let center = bounds.y + bounds.h / 2.0;
+
+        // reversed samples are drawn the way they're played, which is also how their markers count
+        let mut path = vg::Path::new();
+        let columns = (bounds.w / line_width) as usize;
+        for column in 0..columns {
+            let fraction = |column: usize| self.scroll + column as f32 / columns as f32 / self.zoom;
+            let bucket = |fraction: f32| {
+                let bucket =
+                    ((fraction * peaks.buckets.len() as f32) as usize).min(peaks.buckets.len() - 1);
+                if zone.reverse {
+                    peaks.buckets.len() - 1 - bucket
+                } else {
+                    bucket
+                }
+            };
+            let (first, last) = (bucket(fraction(column)), bucket(fraction(column + 1)));
+            let (min, max) = peaks.buckets[first.min(last)..=first.max(last)]
+                .iter()
+                .fold((0.0f32, 0.0f32), |(min, max), (low, high)| {
+                    (min.min(*low), max.max(*high))
+                });
+
+            let x = bounds.x + column as f32 * line_width;
+            path.move_to(x, center - max.clamp(-1.0, 1.0) * bounds.h / 2.0);
+            path.line_to(x, center - min.clamp(-1.0, 1.0) * bounds.h / 2.0);
+        }
+        canvas.stroke_path(&mut path, &paint);
+
+        let mut draw_line = |position: f32, color: vg::Color, top: f32| {
+            let x = self.x_at(position, bounds, peaks.duration);
+            if x < bounds.x || x > bounds.x + bounds.w {
+                return;
+            }
+            let mut path = vg::Path::new();
+            path.move_to(x, top);
+            path.line_to(x, bounds.y + bounds.h);
+            canvas.stroke_path(
+                &mut path,
+                &vg::Paint::color(color).with_line_width(line_width * 2.0),
+            );
+        };
+
+        let has_loop = zone.loop_start.is_some() && zone.loop_end.is_some();
+        for marker in Marker::ALL {
+            let mut color = marker.color();
+            let top = if marker.is_loop() {
+                // the loop markers of samples without a loop are dimmed until they're dragged
+                if !has_loop {
+                    color.set_alphaf(0.4);
+                }
+                center
+            } else {
+                bounds.y
+            };
+            draw_line(marker.position(&zone, peaks.duration), color, top);
+        }
+        for playhead in &self.waveform.playheads {
+            let position = playhead.load(Ordering::Relaxed);
+            if position >= 0.0 {
+                draw_line(position, vg::Color::rgb(240, 30, 30), bounds.y);
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashMap, path::Path};
+
+use base64::Engine;
+use nih_plug::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{library, sample_pool, NihSamplerParams};
+
+/// Total size of the embedded samples above which a warning is shown, as hosts tend to struggle
+/// with very large plugin states
+pub const SIZE_WARNING_BYTES: usize = 64 * 1024 * 1024;
+
+/// FLAC-encoded audio data of a sample, stored in the plugin state so projects still work when the
+/// original file is missing
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmbeddedSample {
+    /// Hash of the file this was encoded from, used to re-embed the sample when the file changed
+    pub content_hash: u64,
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedSample {
+    /// Reads and encodes the file at the given path. FLAC files are embedded as is.
+    pub fn from_file(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        let content_hash = sample_pool::content_hash(&bytes);
+
+        let data = if bytes.starts_with(b"fLaC") {
+            bytes
+        } else {
+            encode_flac(&bytes)?
+        };
+
+        Some(Self { content_hash, data })
+    }
+}
+
+/// Encodes a wav file as FLAC. Integer samples keep their bit depth, everything else is stored as
+/// 24 bit.
+fn encode_flac(wav: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = hound::WavReader::new(wav).ok()?;
+    let spec = reader.spec();
+
+    let (bits_per_sample, samples) = match spec.sample_format {
+        hound::SampleFormat::Int if spec.bits_per_sample <= 24 => (
+            spec.bits_per_sample as usize,
+            reader
+                .samples::<i32>()
+                .map(|s| s.unwrap_or_default())
+                .collect::<Vec<i32>>(),
+        ),
+        hound::SampleFormat::Int => (
+            24,
+            reader
+                .samples::<i32>()
+                .map(|s| s.unwrap_or_default() >> (spec.bits_per_sample - 24))
+                .collect(),
+        ),
+        hound::SampleFormat::Float => (
+            24,
+            reader
+                .samples::<f32>()
+                .map(|s| (s.unwrap_or_default().clamp(-1.0, 1.0) * 8_388_607.0) as i32)
+                .collect(),
+        ),
+    };
+
+    let config = flacenc::config::Encoder::default().into_verified().ok()?;
+    let source = flacenc::source::MemSource::from_samples(
+        &samples,
+        spec.channels as usize,
+        bits_per_sample,
+        spec.sample_rate as usize,
+    );
+    let stream =
+        flacenc::encode_with_fixed_block_size(&config, source, config.block_size).ok()?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    flacenc::component::BitRepr::write(&stream, &mut sink).ok()?;
+
+    Some(sink.as_slice().to_vec())
+}
+
+/// Brings the embedded samples in line with the sample list and the embed setting. This encodes
+/// files, so it runs as a background task.
+pub fn update_embedded_samples(params: &NihSamplerParams) {
+    if !params.embed_samples.value() {
+        params.embedded_samples.lock().unwrap().clear();
+        return;
+    }
+
+    let sample_list = params.sample_paths();
+    let embedded = params.embedded_samples.lock().unwrap().clone();
+
+    let mut encoded = Vec::new();
+    for path in sample_list {
+        let current = embedded.get(&path);
+        let file_path = library::resolve(&path);
+        let up_to_date = match (current, std::fs::read(&file_path)) {
+            (Some(current), Ok(bytes)) => current.content_hash == sample_pool::content_hash(&bytes),
+            // the file is gone, so whatever was embedded is the best there is
+            (Some(_), Err(_)) => true,
+            (None, _) => false,
+        };
+
+        if !up_to_date {
+            if let Some(sample) = EmbeddedSample::from_file(&file_path) {
+                encoded.push((path, sample));
+            }
+        }
+    }
+
+    // the sample list may have changed while encoding, so the results are merged into the current
+    // state instead of replacing it. Samples that were removed or renamed in the meantime don't
+    // come back.
+    let samples = params.samples.lock().unwrap();
+    let in_list = |path: &Path| samples.iter().any(|sample| sample.path.as_path() == path);
+    let mut embedded_samples = params.embedded_samples.lock().unwrap();
+    embedded_samples.retain(|path, _| in_list(path.as_path()));
+    for (path, sample) in encoded {
+        if in_list(path.as_path()) {
+            embedded_samples.insert(path, sample);
+        }
+    }
+
+    let size = total_size(&*embedded_samples);
+    if size > SIZE_WARNING_BYTES {
+        nih_warn!(
+            "embedded samples take up {}, this may slow down saving projects",
+            format_size(size)
+        );
+    }
+}
+
+pub fn total_size<K>(embedded: &HashMap<K, EmbeddedSample>) -> usize {
+    embedded.values().map(|sample| sample.data.len()).sum()
+}
+
+pub fn format_size(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f32 / (1024.0 * 1024.0))
+}
+
+fn to_base64<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use crate::kit::Kit;
+
+pub mod decent_sampler;
+pub mod hydrogen;
+pub mod sf2;
+pub mod sfz;
+
+/// File extensions of the formats that can be imported
+pub const IMPORT_EXTENSIONS: &[&str] = &["sfz", "sf2", "dspreset", "xml"];
+
+/// Imports a kit from another sampler's format. Besides the kit, this returns a list of warnings
+/// about things that couldn't be converted.
+pub fn import(path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "sfz" => sfz::import(path),
+        "sf2" => sf2::import(path),
+        "dspreset" => decent_sampler::import(path),
+        // hydrogen kits are always called drumkit.xml
+        "xml" => hydrogen::import(path),
+        _ => Err(format!("unsupported format: {path:?}")),
+    }
+}
+
+/// Formats a note number like `C#3`, with middle C (60) being `C4`
+pub fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
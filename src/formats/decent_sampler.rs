@@ -0,0 +1,301 @@
+use std::{collections::BTreeMap, path::Path};
+
+use nih_plug::util;
+use roxmltree::Node;
+
+use crate::{
+    formats::note_name,
+    kit::{Envelope, Kit, KitSample, Pad, Zone},
+    library, sample_pool,
+};
+
+/// The attributes of `<groups>`, `<group>` and `<sample>` that are converted when importing, all
+/// others are reported as unsupported
+const SUPPORTED_ATTRIBUTES: &[&str] = &[
+    "name",
+    "path",
+    "rootNote",
+    "loNote",
+    "hiNote",
+    "loVel",
+    "hiVel",
+    "volume",
+    "pan",
+    "tuning",
+    "pitchKeyTrack",
+    "start",
+    "end",
+    "loopEnabled",
+    "loopStart",
+    "loopEnd",
+    "attack",
+    "decay",
+    "sustain",
+    "release",
+    "seqMode",
+    "seqLength",
+    "seqPosition",
+    "trigger",
+    "enabled",
+];
+
+/// Sample rate assumed for offsets when a sample's header can't be read
+const FALLBACK_SAMPLE_RATE: f32 = 44100.0;
+
+/// Imports a DecentSampler preset. Every key used by a sample becomes a pad, with samples that span
+/// multiple keys pitched accordingly. Tags silence samples on other keys, which pads can't do, so
+/// they're reported as unsupported.
+pub fn import(path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&contents, path)
+}
+
+/// Converts the contents of the preset at `path`, with sample paths relative to its folder
+fn parse(contents: &str, path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let document = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("DecentSampler") {
+        return Err("not a DecentSampler preset".into());
+    }
+    let preset_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut warnings = vec![];
+    for element in root.children().filter(Node::is_element) {
+        let tag_name = element.tag_name().name();
+        if !matches!(tag_name, "groups" | "ui") {
+            warnings.push(format!("unsupported element <{tag_name}> is ignored"));
+        }
+    }
+
+    let mut unsupported_attributes = BTreeMap::<String, usize>::new();
+    let mut pads = BTreeMap::<u8, Pad>::new();
+
+    let elements = root.descendants().filter(|node| {
+        node.has_tag_name("groups") || node.has_tag_name("group") || node.has_tag_name("sample")
+    });
+    for attribute in elements.flat_map(|element| element.attributes()) {
+        if !SUPPORTED_ATTRIBUTES.contains(&attribute.name()) {
+            *unsupported_attributes
+                .entry(attribute.name().to_string())
+                .or_default() += 1;
+        }
+    }
+
+    for sample in root
+        .descendants()
+        .filter(|node| node.has_tag_name("sample"))
+    {
+        if inherited(sample, "enabled") == Some("false") {
+            continue;
+        }
+        if inherited(sample, "trigger").is_some_and(|trigger| trigger != "attack") {
+            warnings.push("skipped a release triggered sample".into());
+            continue;
+        }
+        let Some(sample_path) = sample.attribute("path") else {
+            warnings.push("skipped a sample without a path".into());
+            continue;
+        };
+        // presets made on windows use backslashes
+        let sample_path = preset_dir.join(sample_path.replace('\\', "/"));
+
+        let mut value = |attribute: &str, default: f32| -> f32 {
+            match inherited(sample, attribute).map(|value| value.trim().parse::<f32>()) {
+                Some(Ok(value)) => value,
+                Some(Err(_)) => {
+                    warnings.push(format!(
+                        "invalid value for {attribute}: {}",
+                        inherited(sample, attribute).unwrap_or_default()
+                    ));
+                    default
+                }
+                None => default,
+            }
+        };
+
+        // the offsets are in samples of the original file, but zones use seconds
+        let file_sample_rate =
+            sample_pool::file_sample_rate(&sample_path).unwrap_or(FALLBACK_SAMPLE_RATE);
+        let looping = inherited(sample, "loopEnabled") == Some("true");
+        let envelope = ["attack", "decay", "sustain", "release"]
+            .iter()
+            .any(|attribute| inherited(sample, attribute).is_some())
+            .then(|| Envelope {
+                attack: value("attack", 0.0),
+                decay: value("decay", 0.0),
+                sustain: value("sustain", 1.0),
+                release: value("release", 0.0),
+                ..Envelope::default()
+            });
+
+        let round_robin = inherited(sample, "seqMode") == Some("round_robin");
+        let seq_position = value("seqPosition", 1.0).max(1.0) as u32;
+        let seq_length = match inherited(sample, "seqLength") {
+            Some(seq_length) => seq_length.parse().unwrap_or(1),
+            // defaults to the number of positions used in the group
+            None => sample
+                .parent_element()
+                .into_iter()
+                .flat_map(|group| group.children())
+                .filter_map(|sibling| sibling.attribute("seqPosition")?.parse::<u32>().ok())
+                .max()
+                .unwrap_or(1),
+        };
+
+        let zone = Zone {
+            min_velocity: value("loVel", 0.0).clamp(0.0, 127.0) as u8,
+            max_velocity: value("hiVel", 127.0).clamp(0.0, 127.0) as u8,
+            volume: cumulative(sample, "volume", parse_volume),
+            pan: value("pan", 0.0).clamp(-100.0, 100.0),
+            tune: cumulative(sample, "tuning", |tuning| tuning.trim().parse().ok()) * 100.0,
+            start: value("start", 0.0) / file_sample_rate,
+            end: inherited(sample, "end")
+                .and_then(|end| end.trim().parse::<f32>().ok())
+                .map(|end| end / file_sample_rate),
+            loop_start: looping.then(|| value("loopStart", 0.0) / file_sample_rate),
+            loop_end: looping.then(|| value("loopEnd", 0.0) / file_sample_rate),
+            envelope,
+            seq_length: if round_robin { seq_length.max(1) } else { 1 },
+            seq_position: if round_robin { seq_position } else { 1 },
+            ..Zone::default()
+        };
+
+        let root_note = value("rootNote", 60.0) as i32;
+        let lokey = value("loNote", root_note as f32).clamp(0.0, 127.0) as u8;
+        let hikey = value("hiNote", root_note as f32).clamp(0.0, 127.0) as u8;
+        let key_tracking = value("pitchKeyTrack", 1.0);
+        for note in lokey..=hikey {
+            pads.entry(note)
+                .or_insert_with(|| Pad::new(note_name(note), note))
+                .samples
+                .push(KitSample {
+                    path: library::to_portable(&sample_path),
+                    zone: Zone {
+                        tune: zone.tune + (note as i32 - root_note) as f32 * 100.0 * key_tracking,
+                        ..zone.clone()
+                    },
+                });
+        }
+    }
+
+    for (attribute, count) in unsupported_attributes {
+        warnings.push(format!(
+            "unsupported attribute {attribute} is ignored ({count} element(s))"
+        ));
+    }
+
+    let kit = Kit {
+        name: path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        template: None,
+        pads: pads.into_values().collect(),
+    };
+
+    Ok((kit, warnings))
+}
+
+/// Looks up an attribute on a sample, falling back to the enclosing `<group>` and `<groups>`
+fn inherited<'a>(node: Node<'a, '_>, attribute: &str) -> Option<&'a str> {
+    node.ancestors()
+        .find_map(|ancestor| ancestor.attribute(attribute))
+}
+
+/// Sums an attribute that applies at every level, like the volume and tuning
+fn cumulative(node: Node, attribute: &str, parse: impl Fn(&str) -> Option<f32>) -> f32 {
+    node.ancestors()
+        .filter_map(|ancestor| ancestor.attribute(attribute))
+        .filter_map(parse)
+        .sum()
+}
+
+/// Parses a volume given either in dB like `-3dB` or as a linear gain, returning it in dB
+fn parse_volume(volume: &str) -> Option<f32> {
+    let volume = volume.trim();
+    match volume
+        .strip_suffix("dB")
+        .or_else(|| volume.strip_suffix("db"))
+    {
+        Some(db) => db.trim().parse().ok(),
+        None => volume.parse().ok().map(util::gain_to_db),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const PRESET: &str = r#"
+        <DecentSampler>
+          <groups volume="-6dB">
+            <group tags="hats" silencedByTags="hats" tuning="1">
+              <sample path="Samples\hat_closed.wav" rootNote="42" loVel="0" hiVel="63" />
+              <sample path="Samples/hat_open.wav" rootNote="42" loNote="42" hiNote="43"
+                      loVel="64" hiVel="127" volume="0.5" />
+            </group>
+            <group>
+              <sample path="kick.wav" rootNote="36" pan="-20" />
+            </group>
+          </groups>
+        </DecentSampler>
+    "#;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{a} != {b}");
+    }
+
+    #[test]
+    fn converts_keys_to_pads() {
+        let (kit, warnings) = parse(PRESET, Path::new("/kits/test.dspreset")).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                "unsupported attribute silencedByTags is ignored (1 element(s))",
+                "unsupported attribute tags is ignored (1 element(s))",
+            ]
+        );
+        assert_eq!(kit.name, "test");
+
+        let notes = kit.pads.iter().map(|pad| pad.note).collect::<Vec<_>>();
+        assert_eq!(notes, [36, 42, 43]);
+
+        let kick = &kit.pads[0].samples;
+        assert_eq!(kick.len(), 1);
+        assert_eq!(kick[0].path, PathBuf::from("/kits/kick.wav"));
+        assert_close(kick[0].zone.volume, -6.0);
+        assert_close(kick[0].zone.pan, -20.0);
+        assert_eq!(kick[0].zone.group, None);
+    }
+
+    #[test]
+    fn converts_zones() {
+        let (kit, _) = parse(PRESET, Path::new("/kits/test.dspreset")).unwrap();
+
+        let hats = &kit.pads[1].samples;
+        assert_eq!(hats.len(), 2);
+        let (closed, open) = (&hats[0], &hats[1]);
+        assert_eq!(closed.path, PathBuf::from("/kits/Samples/hat_closed.wav"));
+        assert_eq!((closed.zone.min_velocity, closed.zone.max_velocity), (0, 63));
+        assert_eq!((open.zone.min_velocity, open.zone.max_velocity), (64, 127));
+        // the volumes of the group and sample add up
+        assert_close(closed.zone.volume, -6.0);
+        assert_close(open.zone.volume, -6.0 + util::gain_to_db(0.5));
+        assert_close(closed.zone.tune, 100.0);
+        // tags silence samples on other pads, so they don't become choke groups within the pad
+        assert_eq!((closed.zone.group, closed.zone.off_by), (None, None));
+
+        // the key above the root note plays the sample a semitone higher
+        let open_above = &kit.pads[2].samples[0];
+        assert_eq!(open_above.path, open.path);
+        assert_close(open_above.zone.tune, 200.0);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(parse("<drumkit_info/>", Path::new("kit.dspreset")).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{collections::BTreeMap, path::Path};
+
+use nih_plug::util;
+use roxmltree::Node;
+
+use crate::{
+    kit::{Kit, KitSample, Pad, Zone},
+    library,
+};
+
+/// Note of the first instrument when instruments don't specify one, following Hydrogen's own
+/// default mapping
+const FIRST_NOTE: u8 = 36;
+
+/// Imports a Hydrogen `drumkit.xml`. Every instrument becomes a pad, with its layers as velocity
+/// zones. Mute groups choke the other instruments in the group, which pads can't do, so they're
+/// reported as unsupported.
+pub fn import(path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&contents, path)
+}
+
+/// Converts the contents of the drumkit at `path`, with sample paths relative to its folder
+fn parse(contents: &str, path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let document = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("drumkit_info") {
+        return Err("not a Hydrogen drumkit".into());
+    }
+    let kit_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut warnings = vec![];
+    let mut mute_groups = 0;
+    let mut pads = BTreeMap::<u8, Pad>::new();
+
+    let instruments = child(root, "instrumentList")
+        .into_iter()
+        .flat_map(|list| list.children())
+        .filter(|node| node.has_tag_name("instrument"));
+    for (index, instrument) in instruments.enumerate() {
+        let name = text(instrument, "name").unwrap_or_default().to_string();
+        if text(instrument, "isMuted") == Some("true") {
+            warnings.push(format!("skipped muted instrument {name}"));
+            continue;
+        }
+
+        let note = text(instrument, "midiOutNote")
+            .and_then(|note| note.parse::<u8>().ok())
+            .unwrap_or(FIRST_NOTE.saturating_add(index as u8))
+            .min(127);
+        if pads.contains_key(&note) {
+            warnings.push(format!("skipped {name}, note {note} is already used"));
+            continue;
+        }
+
+        let gain = number(instrument, "volume", 1.0) * number(instrument, "gain", 1.0);
+        // older kits store the pan as left and right gains, newer ones as a single value
+        let pan = match child(instrument, "pan") {
+            Some(_) => number(instrument, "pan", 0.0),
+            None => number(instrument, "pan_R", 1.0) - number(instrument, "pan_L", 1.0),
+        };
+        // mute groups start at 0, with -1 meaning no group
+        if text(instrument, "muteGroup")
+            .and_then(|group| group.parse::<i32>().ok())
+            .is_some_and(|group| group >= 0)
+        {
+            mute_groups += 1;
+        }
+
+        // newer kits wrap layers in components, which all play at the same time
+        let components = instrument
+            .children()
+            .filter(|node| node.has_tag_name("instrumentComponent"))
+            .collect::<Vec<_>>();
+        if components.len() > 1 {
+            warnings.push(format!(
+                "{name} has {} components, only the first one is imported",
+                components.len()
+            ));
+        }
+        let layers = components
+            .first()
+            .copied()
+            .unwrap_or(instrument)
+            .children()
+            .filter(|node| node.has_tag_name("layer"));
+
+        let mut pad = Pad::new(name, note);
+        for layer in layers {
+            let Some(file_name) = text(layer, "filename") else {
+                continue;
+            };
+            let sample_path = kit_dir.join(file_name.replace('\\', "/"));
+            let layer_gain = gain * number(layer, "gain", 1.0);
+
+            pad.samples.push(KitSample {
+                path: library::to_portable(&sample_path),
+                zone: Zone {
+                    // layers use velocities between 0 and 1, with the boundaries shared between
+                    // neighbouring layers
+                    min_velocity: (number(layer, "min", 0.0) * 127.0).ceil().clamp(0.0, 127.0)
+                        as u8,
+                    max_velocity: (number(layer, "max", 1.0) * 127.0)
+                        .floor()
+                        .clamp(0.0, 127.0) as u8,
+                    volume: util::gain_to_db(layer_gain),
+                    pan: (pan * 100.0).clamp(-100.0, 100.0),
+                    tune: (number(instrument, "pitchOffset", 0.0) + number(layer, "pitch", 0.0))
+                        * 100.0,
+                    ..Zone::default()
+                },
+            });
+        }
+        pads.insert(note, pad);
+    }
+
+    if mute_groups > 0 {
+        warnings.push(format!(
+            "unsupported muteGroup is ignored ({mute_groups} instrument(s))"
+        ));
+    }
+
+    let kit = Kit {
+        name: text(root, "name").unwrap_or_default().to_string(),
+        template: None,
+        pads: pads.into_values().collect(),
+    };
+
+    Ok((kit, warnings))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, tag_name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(tag_name))
+}
+
+/// The text of a child element
+fn text<'a>(node: Node<'a, '_>, tag_name: &str) -> Option<&'a str> {
+    child(node, tag_name)?.text().map(str::trim)
+}
+
+fn number(node: Node, tag_name: &str, default: f32) -> f32 {
+    text(node, tag_name)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const DRUMKIT: &str = r#"
+        <drumkit_info>
+          <name>Test Kit</name>
+          <instrumentList>
+            <instrument>
+              <name>Kick</name>
+              <volume>0.5</volume>
+              <pitchOffset>-1</pitchOffset>
+              <muteGroup>-1</muteGroup>
+              <layer>
+                <filename>kick_soft.wav</filename>
+                <min>0</min>
+                <max>0.5</max>
+              </layer>
+              <layer>
+                <filename>kick_hard.wav</filename>
+                <min>0.5</min>
+                <max>1</max>
+                <gain>2</gain>
+                <pitch>0.5</pitch>
+              </layer>
+            </instrument>
+            <instrument>
+              <name>Closed Hat</name>
+              <midiOutNote>42</midiOutNote>
+              <muteGroup>0</muteGroup>
+              <instrumentComponent>
+                <layer><filename>hh_closed.wav</filename></layer>
+              </instrumentComponent>
+            </instrument>
+            <instrument>
+              <name>Open Hat</name>
+              <midiOutNote>46</midiOutNote>
+              <muteGroup>0</muteGroup>
+              <pan_L>1</pan_L>
+              <pan_R>0.5</pan_R>
+              <layer><filename>hh_open.wav</filename></layer>
+            </instrument>
+            <instrument>
+              <name>Muted</name>
+              <isMuted>true</isMuted>
+            </instrument>
+          </instrumentList>
+        </drumkit_info>
+    "#;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{a} != {b}");
+    }
+
+    #[test]
+    fn converts_instruments_to_pads() {
+        let (kit, warnings) = parse(DRUMKIT, Path::new("/kits/test/drumkit.xml")).unwrap();
+        assert_eq!(kit.name, "Test Kit");
+        assert_eq!(
+            warnings,
+            [
+                "skipped muted instrument Muted",
+                "unsupported muteGroup is ignored (2 instrument(s))",
+            ]
+        );
+
+        let pads = kit
+            .pads
+            .iter()
+            .map(|pad| (pad.note, pad.name.as_str()))
+            .collect::<Vec<_>>();
+        // instruments without a note follow Hydrogen's default mapping
+        assert_eq!(pads, [(36, "Kick"), (42, "Closed Hat"), (46, "Open Hat")]);
+    }
+
+    #[test]
+    fn converts_layers_to_zones() {
+        let (kit, _) = parse(DRUMKIT, Path::new("/kits/test/drumkit.xml")).unwrap();
+
+        let kick = &kit.pads[0].samples;
+        assert_eq!(kick.len(), 2);
+        let (soft, hard) = (&kick[0], &kick[1]);
+        assert_eq!(soft.path, PathBuf::from("/kits/test/kick_soft.wav"));
+        // the layers share the boundary at 0.5
+        assert_eq!((soft.zone.min_velocity, soft.zone.max_velocity), (0, 63));
+        assert_eq!((hard.zone.min_velocity, hard.zone.max_velocity), (64, 127));
+        // the instrument volume and layer gain are multiplied
+        assert_close(soft.zone.volume, util::gain_to_db(0.5));
+        assert_close(hard.zone.volume, 0.0);
+        // the instrument and layer pitch are added
+        assert_close(soft.zone.tune, -100.0);
+        assert_close(hard.zone.tune, -50.0);
+        assert_eq!(soft.zone.group, None);
+    }
+
+    #[test]
+    fn ignores_mute_groups_and_converts_pan() {
+        let (kit, _) = parse(DRUMKIT, Path::new("/kits/test/drumkit.xml")).unwrap();
+
+        let closed = &kit.pads[1].samples[0];
+        let open = &kit.pads[2].samples[0];
+        assert_eq!(closed.path, PathBuf::from("/kits/test/hh_closed.wav"));
+        // mute groups choke other pads, so they don't become choke groups within the pad
+        assert_eq!((closed.zone.group, closed.zone.off_by), (None, None));
+        assert_eq!((open.zone.group, open.zone.off_by), (None, None));
+        assert_close(closed.zone.pan, 0.0);
+        assert_close(open.zone.pan, -50.0);
+    }
+}
@@ -0,0 +1,852 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    formats::note_name,
+    kit::{Envelope, Kit, KitSample, Pad, Zone},
+    library,
+};
+
+// generator operators from the SoundFont 2.04 specification
+const START_ADDRS_OFFSET: u16 = 0;
+const END_ADDRS_OFFSET: u16 = 1;
+const STARTLOOP_ADDRS_OFFSET: u16 = 2;
+const ENDLOOP_ADDRS_OFFSET: u16 = 3;
+const START_ADDRS_COARSE_OFFSET: u16 = 4;
+const END_ADDRS_COARSE_OFFSET: u16 = 12;
+const PAN: u16 = 17;
+const DELAY_VOL_ENV: u16 = 33;
+const ATTACK_VOL_ENV: u16 = 34;
+const HOLD_VOL_ENV: u16 = 35;
+const DECAY_VOL_ENV: u16 = 36;
+const SUSTAIN_VOL_ENV: u16 = 37;
+const RELEASE_VOL_ENV: u16 = 38;
+const INSTRUMENT: u16 = 41;
+const KEY_RANGE: u16 = 43;
+const VEL_RANGE: u16 = 44;
+const STARTLOOP_ADDRS_COARSE_OFFSET: u16 = 45;
+const INITIAL_ATTENUATION: u16 = 48;
+const ENDLOOP_ADDRS_COARSE_OFFSET: u16 = 50;
+const COARSE_TUNE: u16 = 51;
+const FINE_TUNE: u16 = 52;
+const SAMPLE_ID: u16 = 53;
+const SAMPLE_MODES: u16 = 54;
+const SCALE_TUNING: u16 = 56;
+const OVERRIDING_ROOT_KEY: u16 = 58;
+
+/// The generators that are converted when importing, all others are reported as unsupported
+const SUPPORTED_GENERATORS: &[u16] = &[
+    START_ADDRS_OFFSET,
+    END_ADDRS_OFFSET,
+    STARTLOOP_ADDRS_OFFSET,
+    ENDLOOP_ADDRS_OFFSET,
+    START_ADDRS_COARSE_OFFSET,
+    END_ADDRS_COARSE_OFFSET,
+    PAN,
+    DELAY_VOL_ENV,
+    ATTACK_VOL_ENV,
+    HOLD_VOL_ENV,
+    DECAY_VOL_ENV,
+    SUSTAIN_VOL_ENV,
+    RELEASE_VOL_ENV,
+    INSTRUMENT,
+    KEY_RANGE,
+    VEL_RANGE,
+    STARTLOOP_ADDRS_COARSE_OFFSET,
+    INITIAL_ATTENUATION,
+    ENDLOOP_ADDRS_COARSE_OFFSET,
+    COARSE_TUNE,
+    FINE_TUNE,
+    SAMPLE_ID,
+    SAMPLE_MODES,
+    SCALE_TUNING,
+    OVERRIDING_ROOT_KEY,
+];
+
+/// Generators that are added to the instrument's value when they're set on a preset
+const ADDITIVE_GENERATORS: &[u16] = &[
+    PAN,
+    DELAY_VOL_ENV,
+    ATTACK_VOL_ENV,
+    HOLD_VOL_ENV,
+    DECAY_VOL_ENV,
+    SUSTAIN_VOL_ENV,
+    RELEASE_VOL_ENV,
+    INITIAL_ATTENUATION,
+    COARSE_TUNE,
+    FINE_TUNE,
+];
+
+const SAMPLE_TYPE_RIGHT: u16 = 2;
+const SAMPLE_TYPE_LEFT: u16 = 4;
+const SAMPLE_TYPE_ROM: u16 = 0x8000;
+
+/// Imports a SoundFont. The samples are stored in the SoundFont itself, but kits refer to sample
+/// files, so the samples used by the preset are extracted as wav files into a folder next to it.
+///
+/// Only one preset is imported, preferring the drum kits in bank 128. Every key used by the preset
+/// becomes a pad, with zones that span multiple keys pitched accordingly and zones without a key
+/// range added to the pad of their root key. Exclusive classes choke other keys, which pads can't
+/// do, so they're reported as unsupported.
+pub fn import(path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let sound_font = SoundFont::parse(&bytes)?;
+
+    let mut warnings = vec![];
+    let preset = sound_font
+        .presets
+        .iter()
+        .min_by_key(|preset| (preset.bank != 128, preset.bank, preset.program))
+        .ok_or("the SoundFont doesn't contain any presets")?;
+    if sound_font.presets.len() > 1 {
+        warnings.push(format!(
+            "only imported preset {} ({}:{}), the other {} are ignored",
+            preset.name,
+            preset.bank,
+            preset.program,
+            sound_font.presets.len() - 1
+        ));
+    }
+    if sound_font.has_modulators {
+        warnings.push("modulators are ignored".into());
+    }
+
+    let sample_dir = path.with_file_name(format!(
+        "{} samples",
+        path.file_stem().unwrap_or_default().to_string_lossy()
+    ));
+    let mut extracted = HashMap::<usize, PathBuf>::new();
+    let mut unsupported_generators = BTreeMap::<u16, usize>::new();
+    let mut ignored_preset_generators = BTreeMap::<u16, usize>::new();
+    let mut pads = BTreeMap::<u8, Pad>::new();
+
+    for generators in sound_font.zones(preset, &mut ignored_preset_generators) {
+        for operator in generators.keys() {
+            if !SUPPORTED_GENERATORS.contains(operator) {
+                *unsupported_generators.entry(*operator).or_default() += 1;
+            }
+        }
+
+        let Some(sample_index) = generators
+            .get(&SAMPLE_ID)
+            .map(|&amount| amount as u16 as usize)
+        else {
+            continue;
+        };
+        let Some(sample) = sound_font.samples.get(sample_index) else {
+            warnings.push(format!("skipped a zone with invalid sample {sample_index}"));
+            continue;
+        };
+        if sample.sample_type & SAMPLE_TYPE_ROM != 0 {
+            warnings.push(format!("skipped ROM sample {}", sample.name));
+            continue;
+        }
+        // the left and right halves of a stereo sample are extracted together
+        let stereo_pair = match sample.sample_type {
+            SAMPLE_TYPE_RIGHT => continue,
+            SAMPLE_TYPE_LEFT => sound_font.samples.get(sample.link as usize),
+            _ => None,
+        };
+
+        let sample_path = match extracted.get(&sample_index) {
+            Some(sample_path) => sample_path.clone(),
+            None => {
+                let sample_path = sample_dir.join(format!(
+                    "{sample_index:03} {}.wav",
+                    sanitize_file_name(&sample.name)
+                ));
+                sound_font.extract(sample, stereo_pair, &sample_path)?;
+                extracted.insert(sample_index, sample_path.clone());
+                sample_path
+            }
+        };
+
+        let value = |operator: u16, default: i16| -> i32 {
+            generators.get(&operator).map_or(default, |&amount| amount) as i32
+        };
+        let (lovel, hivel) = range(generators.get(&VEL_RANGE));
+        let sample_rate = sample.sample_rate.max(1) as f32;
+        let offset = |fine: u16, coarse: u16| value(fine, 0) + value(coarse, 0) * 32768;
+
+        let start = offset(START_ADDRS_OFFSET, START_ADDRS_COARSE_OFFSET).max(0);
+        let len = sample.end.saturating_sub(sample.start) as i32;
+        let end = (len + offset(END_ADDRS_OFFSET, END_ADDRS_COARSE_OFFSET)).clamp(0, len);
+        let loop_start = (sample.loop_start as i32 - sample.start as i32
+            + offset(STARTLOOP_ADDRS_OFFSET, STARTLOOP_ADDRS_COARSE_OFFSET))
+        .max(0);
+        let loop_end = sample.loop_end as i32 - sample.start as i32
+            + offset(ENDLOOP_ADDRS_OFFSET, ENDLOOP_ADDRS_COARSE_OFFSET);
+        // 1 loops continuously, 3 loops until the note is released
+        let looping = matches!(value(SAMPLE_MODES, 0), 1 | 3) && loop_end > loop_start;
+
+        let envelope_generators = [
+            DELAY_VOL_ENV,
+            ATTACK_VOL_ENV,
+            HOLD_VOL_ENV,
+            DECAY_VOL_ENV,
+            SUSTAIN_VOL_ENV,
+            RELEASE_VOL_ENV,
+        ];
+        let envelope = envelope_generators
+            .iter()
+            .any(|operator| generators.contains_key(operator))
+            .then(|| Envelope {
+                delay: timecents_to_seconds(value(DELAY_VOL_ENV, -12000)),
+                attack: timecents_to_seconds(value(ATTACK_VOL_ENV, -12000)),
+                hold: timecents_to_seconds(value(HOLD_VOL_ENV, -12000)),
+                decay: timecents_to_seconds(value(DECAY_VOL_ENV, -12000)),
+                // the sustain is an attenuation in centibels
+                sustain: 10.0f32.powf(-(value(SUSTAIN_VOL_ENV, 0).clamp(0, 1440) as f32) / 200.0),
+                release: timecents_to_seconds(value(RELEASE_VOL_ENV, -12000)),
+            });
+
+        let root_key = match value(OVERRIDING_ROOT_KEY, -1) {
+            -1 => sample.original_pitch as i32,
+            root_key => root_key,
+        };
+        // a zone without a key range would otherwise fill every pad, like an SFZ region without
+        // keys it goes on the pad of its root key. Unpitched samples have a root key of 255.
+        let (lokey, hikey) = match generators.get(&KEY_RANGE) {
+            Some(key_range) => range(Some(key_range)),
+            None => {
+                let key = u8::try_from(root_key)
+                    .ok()
+                    .filter(|&key| key <= 127)
+                    .unwrap_or(60);
+                (key, key)
+            }
+        };
+        let tune = value(COARSE_TUNE, 0) * 100 + value(FINE_TUNE, 0) + sample.pitch_correction;
+
+        let zone = Zone {
+            min_velocity: lovel,
+            max_velocity: hivel,
+            volume: -(value(INITIAL_ATTENUATION, 0) as f32) / 10.0,
+            // stereo samples are panned hard left and right, which doesn't apply once they're merged
+            pan: if stereo_pair.is_some() {
+                0.0
+            } else {
+                value(PAN, 0).clamp(-500, 500) as f32 / 5.0
+            },
+            tune: 0.0,
+            start: start as f32 / sample_rate,
+            end: (end < len).then(|| end as f32 / sample_rate),
+            loop_start: looping.then(|| loop_start as f32 / sample_rate),
+            loop_end: looping.then(|| loop_end as f32 / sample_rate),
+            envelope,
+            ..Zone::default()
+        };
+
+        for note in lokey..=hikey {
+            let key_tracking = (note as i32 - root_key) * value(SCALE_TUNING, 100);
+            pads.entry(note)
+                .or_insert_with(|| Pad::new(note_name(note), note))
+                .samples
+                .push(KitSample {
+                    path: library::to_portable(&sample_path),
+                    zone: Zone {
+                        tune: (tune + key_tracking) as f32,
+                        ..zone.clone()
+                    },
+                });
+        }
+    }
+
+    for (operator, count) in unsupported_generators {
+        warnings.push(format!(
+            "unsupported generator {operator} is ignored ({count} zone(s))"
+        ));
+    }
+    for (operator, count) in ignored_preset_generators {
+        warnings.push(format!(
+            "unsupported preset generator {operator} is ignored ({count} zone(s))"
+        ));
+    }
+
+    let kit = Kit {
+        name: preset.name.clone(),
+        template: None,
+        pads: pads.into_values().collect(),
+    };
+
+    Ok((kit, warnings))
+}
+
+struct Preset {
+    name: String,
+    program: u16,
+    bank: u16,
+    /// Indices into the preset zones
+    zones: std::ops::Range<usize>,
+}
+
+struct SampleHeader {
+    name: String,
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    original_pitch: u8,
+    pitch_correction: i32,
+    link: u16,
+    sample_type: u16,
+}
+
+/// A zone's generators, by operator
+type Generators = HashMap<u16, i16>;
+
+struct SoundFont<'a> {
+    presets: Vec<Preset>,
+    preset_zones: Vec<Generators>,
+    /// Instruments as ranges of instrument zones
+    instruments: Vec<std::ops::Range<usize>>,
+    instrument_zones: Vec<Generators>,
+    samples: Vec<SampleHeader>,
+    has_modulators: bool,
+    /// 16 bit sample data
+    smpl: &'a [u8],
+    /// The lower 8 bits of 24 bit sample data, if present
+    sm24: Option<&'a [u8]>,
+}
+
+impl<'a> SoundFont<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"sfbk" {
+            return Err("not a SoundFont 2 file".into());
+        }
+
+        let mut lists = HashMap::new();
+        for (id, data) in chunks(&bytes[12..]) {
+            if id == b"LIST" && data.len() >= 4 {
+                lists.insert(&data[0..4], chunks(&data[4..]).collect::<HashMap<_, _>>());
+            }
+        }
+        let sdta = lists.get(&b"sdta"[..]).ok_or("missing sample data")?;
+        let pdta = lists.get(&b"pdta"[..]).ok_or("missing preset data")?;
+        let pdta_chunk = |id: &[u8; 4], record_size: usize| -> Result<Vec<&[u8]>, String> {
+            let data = pdta
+                .get(&id[..])
+                .ok_or_else(|| format!("missing {} chunk", String::from_utf8_lossy(id)))?;
+            Ok(data.chunks_exact(record_size).collect())
+        };
+
+        // the last record of every list is a terminator, which is only used for its indices
+        let phdr = pdta_chunk(b"phdr", 38)?;
+        let pbag = pdta_chunk(b"pbag", 4)?;
+        let pgen = pdta_chunk(b"pgen", 4)?;
+        let inst = pdta_chunk(b"inst", 22)?;
+        let ibag = pdta_chunk(b"ibag", 4)?;
+        let igen = pdta_chunk(b"igen", 4)?;
+        let shdr = pdta_chunk(b"shdr", 46)?;
+        let has_modulators = [b"pmod", b"imod"]
+            .iter()
+            .any(|id| pdta.get(&id[..]).is_some_and(|data| data.len() > 10));
+
+        let presets = phdr
+            .windows(2)
+            .map(|records| Preset {
+                name: name(&records[0][0..20]),
+                program: u16_at(records[0], 20),
+                bank: u16_at(records[0], 22),
+                zones: u16_at(records[0], 24) as usize..u16_at(records[1], 24) as usize,
+            })
+            .collect();
+        let instruments = inst
+            .windows(2)
+            .map(|records| u16_at(records[0], 20) as usize..u16_at(records[1], 20) as usize)
+            .collect();
+        let samples = shdr
+            .iter()
+            .take(shdr.len().saturating_sub(1))
+            .map(|record| SampleHeader {
+                name: name(&record[0..20]),
+                start: u32_at(record, 20),
+                end: u32_at(record, 24),
+                loop_start: u32_at(record, 28),
+                loop_end: u32_at(record, 32),
+                sample_rate: u32_at(record, 36),
+                original_pitch: record[40],
+                pitch_correction: record[41] as i8 as i32,
+                link: u16_at(record, 42),
+                sample_type: u16_at(record, 44),
+            })
+            .collect();
+
+        Ok(Self {
+            presets,
+            preset_zones: zones(&pbag, &pgen),
+            instruments,
+            instrument_zones: zones(&ibag, &igen),
+            samples,
+            has_modulators,
+            smpl: sdta.get(&b"smpl"[..]).copied().unwrap_or_default(),
+            sm24: sdta.get(&b"sm24"[..]).copied(),
+        })
+    }
+
+    /// The generators of every instrument zone used by the preset, combined with the preset's
+    /// generators. Global zones are merged into the zones that follow them. Preset generators
+    /// that can't be combined are counted in `ignored` for every zone they would have applied to.
+    fn zones(&self, preset: &Preset, ignored: &mut BTreeMap<u16, usize>) -> Vec<Generators> {
+        let mut result = vec![];
+
+        let preset_zones = self
+            .preset_zones
+            .get(preset.zones.clone())
+            .unwrap_or_default();
+        let (preset_global, preset_zones) = split_global(preset_zones, INSTRUMENT);
+        for preset_zone in preset_zones {
+            let mut preset_generators = preset_global.clone();
+            preset_generators.extend(preset_zone);
+
+            let Some(instrument) = preset_generators
+                .get(&INSTRUMENT)
+                .and_then(|&instrument| self.instruments.get(instrument as u16 as usize))
+            else {
+                continue;
+            };
+            let instrument_zones = self
+                .instrument_zones
+                .get(instrument.clone())
+                .unwrap_or_default();
+            let (instrument_global, instrument_zones) = split_global(instrument_zones, SAMPLE_ID);
+
+            for instrument_zone in instrument_zones {
+                let mut generators = instrument_global.clone();
+                generators.extend(instrument_zone);
+
+                for (&operator, &amount) in &preset_generators {
+                    match operator {
+                        KEY_RANGE | VEL_RANGE => {
+                            let (lo, hi) = range(generators.get(&operator));
+                            let (preset_lo, preset_hi) = range(Some(&amount));
+                            generators.insert(
+                                operator,
+                                i16::from_le_bytes([lo.max(preset_lo), hi.min(preset_hi)]),
+                            );
+                        }
+                        operator if ADDITIVE_GENERATORS.contains(&operator) => {
+                            let sum = generators.get(&operator).copied().unwrap_or_default() as i32
+                                + amount as i32;
+                            generators.insert(operator, sum.clamp(-32768, 32767) as i16);
+                        }
+                        INSTRUMENT => {}
+                        operator => *ignored.entry(operator).or_default() += 1,
+                    }
+                }
+
+                let (lo, hi) = range(generators.get(&KEY_RANGE));
+                let (lovel, hivel) = range(generators.get(&VEL_RANGE));
+                if lo <= hi && lovel <= hivel {
+                    result.push(generators);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Writes a sample as a wav file, together with its right channel if it's part of a stereo
+    /// pair
+    fn extract(
+        &self,
+        sample: &SampleHeader,
+        right: Option<&SampleHeader>,
+        path: &Path,
+    ) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+
+        let channels = [Some(sample), right]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let spec = hound::WavSpec {
+            channels: channels.len() as u16,
+            sample_rate: sample.sample_rate,
+            bits_per_sample: if self.sm24.is_some() { 24 } else { 16 },
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+
+        let len = channels
+            .iter()
+            .map(|channel| channel.end.saturating_sub(channel.start))
+            .min()
+            .unwrap_or_default();
+        for frame in 0..len {
+            for channel in &channels {
+                let index = (channel.start + frame) as usize;
+                let high = self
+                    .smpl
+                    .get(index * 2..index * 2 + 2)
+                    .map_or(0, |bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as i32);
+                let value = match self.sm24 {
+                    Some(sm24) => (high << 8) | sm24.get(index).copied().unwrap_or_default() as i32,
+                    None => high,
+                };
+                writer.write_sample(value).map_err(|e| e.to_string())?;
+            }
+        }
+
+        writer.finalize().map_err(|e| e.to_string())
+    }
+}
+
+/// Iterates over the chunks in a RIFF list as ids and data
+fn chunks(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let id = &data[0..4];
+        let len = (u32_at(data, 4) as usize).min(data.len() - 8);
+        let chunk = &data[8..8 + len];
+        // chunks are padded to an even length
+        data = &data[(8 + len + len % 2).min(data.len())..];
+        Some((id, chunk))
+    })
+}
+
+/// Collects the generators of every zone from a bag and generator chunk
+fn zones(bags: &[&[u8]], generators: &[&[u8]]) -> Vec<Generators> {
+    bags.windows(2)
+        .map(|bags| {
+            let range = u16_at(bags[0], 0) as usize..u16_at(bags[1], 0) as usize;
+            generators
+                .get(range)
+                .unwrap_or_default()
+                .iter()
+                .map(|generator| {
+                    (
+                        u16_at(generator, 0),
+                        i16::from_le_bytes([generator[2], generator[3]]),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Splits off the global zone, which is the first zone if it doesn't end in `terminal_generator`
+fn split_global(zones: &[Generators], terminal_generator: u16) -> (Generators, &[Generators]) {
+    match zones.first() {
+        Some(first) if !first.contains_key(&terminal_generator) => (first.clone(), &zones[1..]),
+        _ => (Generators::new(), zones),
+    }
+}
+
+/// Reads a key or velocity range generator, which stores the low and high value as two bytes
+fn range(amount: Option<&i16>) -> (u8, u8) {
+    match amount {
+        Some(amount) => {
+            let [lo, hi] = amount.to_le_bytes();
+            (lo.min(127), hi.min(127))
+        }
+        None => (0, 127),
+    }
+}
+
+fn timecents_to_seconds(timecents: i32) -> f32 {
+    // the minimum value means instantaneous
+    if timecents <= -12000 {
+        0.0
+    } else {
+        2.0f32.powf(timecents as f32 / 1200.0)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Reads a zero terminated name
+fn name(data: &[u8]) -> String {
+    let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..len]).trim().to_string()
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn list(id: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[id.to_vec(), chunks.concat()].concat())
+    }
+
+    fn name_field(name: &str) -> Vec<u8> {
+        let mut field = name.as_bytes().to_vec();
+        field.resize(20, 0);
+        field
+    }
+
+    fn preset(name: &str, program: u16, bank: u16, bag: u16) -> Vec<u8> {
+        let mut record = name_field(name);
+        for value in [program, bank, bag] {
+            record.extend(value.to_le_bytes());
+        }
+        // library, genre and morphology
+        record.extend([0; 12]);
+        record
+    }
+
+    fn instrument(name: &str, bag: u16) -> Vec<u8> {
+        [name_field(name), bag.to_le_bytes().to_vec()].concat()
+    }
+
+    fn sample(
+        name: &str,
+        start: u32,
+        end: u32,
+        loop_range: (u32, u32),
+        key: u8,
+        cents: i8,
+    ) -> Vec<u8> {
+        let mut record = name_field(name);
+        for value in [start, end, loop_range.0, loop_range.1, 22050] {
+            record.extend(value.to_le_bytes());
+        }
+        record.extend([key, cents as u8]);
+        // no link, mono
+        record.extend(0u16.to_le_bytes());
+        record.extend(1u16.to_le_bytes());
+        record
+    }
+
+    /// The bags' indices into the generators, ending with the terminator
+    fn bags(starts: &[u16]) -> Vec<u8> {
+        starts
+            .iter()
+            .flat_map(|start| [start.to_le_bytes(), [0, 0]].concat())
+            .collect()
+    }
+
+    /// Generator records followed by the terminator
+    fn generators(generators: &[(u16, i16)]) -> Vec<u8> {
+        generators
+            .iter()
+            .chain([&(0, 0)])
+            .flat_map(|(operator, amount)| [operator.to_le_bytes(), amount.to_le_bytes()].concat())
+            .collect()
+    }
+
+    fn range_amount(lo: u8, hi: u8) -> i16 {
+        i16::from_le_bytes([lo, hi])
+    }
+
+    /// A drum kit preset with a kick, a hi-hat and a keyless zone in one instrument, and a second
+    /// preset using the same instrument
+    fn sound_font() -> Vec<u8> {
+        let smpl: Vec<u8> = (0..8i16)
+            .map(|i| i * 1000)
+            .chain([-1, -2, -3, -4])
+            .flat_map(i16::to_le_bytes)
+            .collect();
+
+        let pdta = [
+            chunk(
+                b"phdr",
+                &[
+                    preset("Standard", 0, 128, 0),
+                    preset("Piano", 0, 0, 2),
+                    preset("EOP", 0, 0, 3),
+                ]
+                .concat(),
+            ),
+            chunk(b"pbag", &bags(&[0, 2, 5, 6])),
+            chunk(b"pmod", &[0; 10]),
+            chunk(
+                b"pgen",
+                &generators(&[
+                    // global zone, the start offset can't be combined with the instrument's
+                    (FINE_TUNE, 10),
+                    (START_ADDRS_OFFSET, 1),
+                    (VEL_RANGE, range_amount(10, 100)),
+                    (INITIAL_ATTENUATION, 10),
+                    (INSTRUMENT, 0),
+                    (INSTRUMENT, 0),
+                ]),
+            ),
+            chunk(
+                b"inst",
+                &[instrument("Drums", 0), instrument("EOI", 4)].concat(),
+            ),
+            chunk(b"ibag", &bags(&[0, 2, 5, 9, 11])),
+            chunk(b"imod", &[0; 10]),
+            chunk(
+                b"igen",
+                &generators(&[
+                    // global zone
+                    (INITIAL_ATTENUATION, 60),
+                    (PAN, -250),
+                    (KEY_RANGE, range_amount(36, 36)),
+                    (SAMPLE_MODES, 1),
+                    (SAMPLE_ID, 0),
+                    (KEY_RANGE, range_amount(42, 42)),
+                    (INITIAL_ATTENUATION, 30),
+                    // an exclusive class, which also chokes other keys
+                    (57, 1),
+                    (SAMPLE_ID, 1),
+                    // no key range, played on its root key only
+                    (OVERRIDING_ROOT_KEY, 38),
+                    (SAMPLE_ID, 0),
+                ]),
+            ),
+            chunk(
+                b"shdr",
+                &[
+                    sample("Kick", 0, 8, (2, 6), 36, 0),
+                    sample("Hat", 8, 12, (8, 12), 42, -5),
+                    sample("EOS", 0, 0, (0, 0), 0, 0),
+                ]
+                .concat(),
+            ),
+        ];
+
+        let body = [
+            b"sfbk".to_vec(),
+            list(b"INFO", &[chunk(b"INAM", b"Kit")]),
+            // odd length, so the chunks after it are only found when the padding is skipped
+            chunk(b"junk", b"abc"),
+            list(b"sdta", &[chunk(b"smpl", &smpl)]),
+            list(b"pdta", &pdta),
+        ]
+        .concat();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn walks_padded_chunks() {
+        let data = [chunk(b"abcd", b"xyz"), chunk(b"efgh", b"")].concat();
+        let found: Vec<_> = chunks(&data).collect();
+        assert_eq!(
+            found,
+            [(&b"abcd"[..], &b"xyz"[..]), (&b"efgh"[..], &b""[..])]
+        );
+
+        // a length past the end is cut off at the end of the data
+        let mut truncated = chunk(b"abcd", b"xyz");
+        truncated[4] = 100;
+        let found: Vec<_> = chunks(&truncated).collect();
+        assert_eq!(found, [(&b"abcd"[..], &b"xyz\0"[..])]);
+    }
+
+    #[test]
+    fn imports_the_drum_preset() {
+        let dir = std::env::temp_dir().join("nih-sampler-sf2-import");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kit.sf2");
+        std::fs::write(&path, sound_font()).unwrap();
+
+        let (kit, warnings) = import(&path).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                "only imported preset Standard (128:0), the other 1 are ignored",
+                "unsupported generator 57 is ignored (1 zone(s))",
+                "unsupported preset generator 0 is ignored (3 zone(s))",
+            ]
+        );
+        assert_eq!(kit.name, "Standard");
+        assert_eq!(kit.pads.len(), 3);
+        let (kick, keyless, hat) = (&kit.pads[0], &kit.pads[1], &kit.pads[2]);
+        assert_eq!((kick.note, keyless.note, hat.note), (36, 38, 42));
+
+        // the global zones are merged in, and the preset's generators added to the instrument's
+        assert_eq!(kick.samples.len(), 1);
+        assert_eq!(
+            kick.samples[0].zone,
+            Zone {
+                min_velocity: 10,
+                max_velocity: 100,
+                volume: -7.0,
+                pan: -50.0,
+                tune: 10.0,
+                loop_start: Some(2.0 / 22050.0),
+                loop_end: Some(6.0 / 22050.0),
+                ..Zone::default()
+            }
+        );
+        // the keyless zone shares the kick's sample, without the kick's loop or key tracking
+        assert_eq!(keyless.samples.len(), 1);
+        assert_eq!(
+            keyless.samples[0].zone,
+            Zone {
+                min_velocity: 10,
+                max_velocity: 100,
+                volume: -7.0,
+                pan: -50.0,
+                tune: 10.0,
+                ..Zone::default()
+            }
+        );
+        assert_eq!(hat.samples.len(), 1);
+        assert_eq!(
+            hat.samples[0].zone,
+            Zone {
+                min_velocity: 10,
+                max_velocity: 100,
+                volume: -4.0,
+                pan: -50.0,
+                // the preset's fine tune and the sample's pitch correction
+                tune: 5.0,
+                ..Zone::default()
+            }
+        );
+
+        let sample_dir = dir.join("kit samples");
+        assert_eq!(kick.samples[0].path, sample_dir.join("000 Kick.wav"));
+        assert_eq!(keyless.samples[0].path, kick.samples[0].path);
+        assert_eq!(hat.samples[0].path, sample_dir.join("001 Hat.wav"));
+        let read = |path: &Path| {
+            let mut reader = hound::WavReader::open(path).unwrap();
+            let spec = reader.spec();
+            assert_eq!((spec.channels, spec.sample_rate), (1, 22050));
+            reader
+                .samples::<i32>()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            read(&kick.samples[0].path),
+            [0, 1000, 2000, 3000, 4000, 5000, 6000, 7000]
+        );
+        assert_eq!(read(&hat.samples[0].path), [-1, -2, -3, -4]);
+    }
+}
@@ -0,0 +1,744 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::Path,
+};
+
+use nih_plug::util;
+
+use crate::{
+    formats::note_name,
+    kit::{Envelope, HiHat, Kit, KitSample, Pad, Zone},
+    library, sample_pool,
+};
+
+/// The opcodes that are converted when importing, all others are reported as unsupported. The
+/// hi-hat pedal's opcodes are matched by `pedal_cc()`.
+const SUPPORTED_OPCODES: &[&str] = &[
+    "sample",
+    "default_path",
+    "key",
+    "lokey",
+    "hikey",
+    // only used as the key of regions without one, pitch tracking isn't supported
+    "pitch_keycenter",
+    "lovel",
+    "hivel",
+    "seq_length",
+    "seq_position",
+    "lorand",
+    "hirand",
+    "group",
+    "off_by",
+    "offset",
+    "end",
+    "volume",
+    "pan",
+    "tune",
+    "transpose",
+    "loop_mode",
+    "direction",
+    "loop_start",
+    "loop_end",
+    "ampeg_delay",
+    "ampeg_attack",
+    "ampeg_hold",
+    "ampeg_decay",
+    "ampeg_sustain",
+    "ampeg_release",
+];
+
+/// Sample rate assumed for offsets when a sample's header can't be read
+const FALLBACK_SAMPLE_RATE: f32 = 44100.0;
+
+/// Imports an SFZ file. Every key used by a region becomes a pad, regions spanning multiple keys
+/// are added to all of them and regions without keys to the pad of their `pitch_keycenter`.
+pub fn import(path: &Path) -> Result<(Kit, Vec<String>), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let sfz_dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut warnings = vec![];
+    let mut unsupported_opcodes = BTreeMap::<String, usize>::new();
+    let mut pads = BTreeMap::<u8, Pad>::new();
+
+    for region in parse(&contents, &mut warnings) {
+        for opcode in region.keys() {
+            if !SUPPORTED_OPCODES.contains(&opcode.as_str()) && pedal_cc(opcode).is_none() {
+                *unsupported_opcodes.entry(opcode.clone()).or_default() += 1;
+            }
+        }
+
+        let Some(sample) = region.get("sample") else {
+            warnings.push("skipped a region without a sample".into());
+            continue;
+        };
+        // sfz files written on windows use backslashes
+        let sample_path = sfz_dir
+            .join(
+                region
+                    .get("default_path")
+                    .map_or("", String::as_str)
+                    .replace('\\', "/"),
+            )
+            .join(sample.replace('\\', "/"));
+
+        // CC 4 is the hi-hat pedal by convention, but any CC can be used
+        let mut pedal_ccs: Vec<u8> = region
+            .keys()
+            .filter_map(|opcode| pedal_cc(opcode))
+            .collect();
+        pedal_ccs.sort_unstable();
+        pedal_ccs.dedup();
+        let pedal_cc = pedal_ccs.first().copied().unwrap_or(4);
+        if pedal_ccs.len() > 1 {
+            warnings.push(format!(
+                "{sample} depends on CCs {pedal_ccs:?}, only CC {pedal_cc} is imported"
+            ));
+        }
+        let [locc, hicc, on_locc, on_hicc] =
+            ["locc", "hicc", "on_locc", "on_hicc"].map(|opcode| format!("{opcode}{pedal_cc}"));
+
+        let mut value = |opcode: &str, default: f32| -> f32 {
+            match region.get(opcode).map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) => value,
+                Some(Err(_)) => {
+                    warnings.push(format!("invalid value for {opcode}: {}", region[opcode]));
+                    default
+                }
+                None => default,
+            }
+        };
+
+        // the offsets are in samples of the original file, but zones use seconds
+        let file_sample_rate =
+            sample_pool::file_sample_rate(&sample_path).unwrap_or(FALLBACK_SAMPLE_RATE);
+        let end = value("end", -2.0);
+        if end == -1.0 {
+            // an end of -1 disables the region
+            continue;
+        }
+
+        let looping = matches!(
+            region.get("loop_mode").map(String::as_str),
+            Some("loop_continuous" | "loop_sustain")
+        );
+        // without a loop end the loop runs to the end of the region, or of the file
+        let loop_end = looping
+            .then(|| {
+                if region.contains_key("loop_end") {
+                    Some(value("loop_end", 0.0) / file_sample_rate)
+                } else if end >= 0.0 {
+                    Some(end / file_sample_rate)
+                } else {
+                    sample_pool::file_duration(&sample_path)
+                }
+            })
+            .flatten();
+        let envelope = region
+            .keys()
+            .any(|opcode| opcode.starts_with("ampeg_"))
+            .then(|| Envelope {
+                delay: value("ampeg_delay", 0.0),
+                attack: value("ampeg_attack", 0.0),
+                hold: value("ampeg_hold", 0.0),
+                decay: value("ampeg_decay", 0.0),
+                sustain: value("ampeg_sustain", 100.0) / 100.0,
+                release: value("ampeg_release", 0.0),
+            });
+
+        let zone = Zone {
+            min_velocity: value("lovel", 0.0).clamp(0.0, 127.0) as u8,
+            max_velocity: value("hivel", 127.0).clamp(0.0, 127.0) as u8,
+            min_openness: value(&locc, 0.0).clamp(0.0, 127.0) as u8,
+            max_openness: value(&hicc, 127.0).clamp(0.0, 127.0) as u8,
+            pedal_chick: region.contains_key(&on_locc) || region.contains_key(&on_hicc),
+            volume: value("volume", 0.0),
+            pan: value("pan", 0.0),
+            tune: value("tune", 0.0) + value("transpose", 0.0) * 100.0,
+            start: value("offset", 0.0) / file_sample_rate,
+            end: (end >= 0.0).then(|| end / file_sample_rate),
+            loop_start: loop_end.map(|_| value("loop_start", 0.0) / file_sample_rate),
+            loop_end,
+            envelope,
+            seq_length: value("seq_length", 1.0).max(1.0) as u32,
+            seq_position: value("seq_position", 1.0).max(1.0) as u32,
+            lorand: value("lorand", 0.0),
+            hirand: value("hirand", 1.0),
+            group: region.get("group").and_then(|value| value.parse().ok()),
+            off_by: region.get("off_by").and_then(|value| value.parse().ok()),
+            weight: 1.0,
+            reverse: region
+                .get("direction")
+                .is_some_and(|value| value == "reverse"),
+        };
+
+        // the chick plays when the pedal closes below the threshold, so its range starts at 0
+        let close_threshold = (zone.pedal_chick && region.contains_key(&on_hicc))
+            .then(|| (value(&on_hicc, 127.0) + 1.0).clamp(1.0, 127.0) as u8);
+        let chick_low = value(&on_locc, 0.0);
+        if zone.pedal_chick && chick_low > 0.0 {
+            warnings.push(format!(
+                "{sample} plays from pedal position {chick_low}, but is imported as playing from 0"
+            ));
+        }
+        if looping && loop_end.is_none() {
+            warnings.push(format!(
+                "{sample} loops to the end of the file, which can't be read, so it doesn't loop"
+            ));
+        }
+
+        // regions without keys cover the whole keyboard, but that would make a pad for every key
+        let key = region.get("key").and_then(|key| parse_key(key));
+        let lokey = region.get("lokey").and_then(|key| parse_key(key)).or(key);
+        let hikey = region.get("hikey").and_then(|key| parse_key(key)).or(key);
+        let (lokey, hikey) = match (lokey, hikey) {
+            (None, None) => {
+                let center = region.get("pitch_keycenter").and_then(|key| parse_key(key));
+                (center.unwrap_or(60), center.unwrap_or(60))
+            }
+            (lokey, hikey) => (lokey.unwrap_or(0), hikey.unwrap_or(127)),
+        };
+        if lokey > hikey {
+            warnings.push(format!(
+                "skipped {sample}, its lowest key {lokey} is above its highest key {hikey}"
+            ));
+            continue;
+        }
+        if hikey > lokey {
+            warnings.push(format!(
+                "{sample} spans keys {lokey}-{hikey} and is added to each key without pitch tracking"
+            ));
+        }
+
+        for note in lokey..=hikey {
+            let pad = pads
+                .entry(note)
+                .or_insert_with(|| Pad::new(note_name(note), note));
+            if zone.pedal_chick || zone.min_openness > 0 || zone.max_openness < 127 {
+                let hi_hat = pad.hi_hat.get_or_insert_with(HiHat::default);
+                hi_hat.cc = pedal_cc;
+                if let Some(close_threshold) = close_threshold {
+                    hi_hat.close_threshold = close_threshold;
+                }
+            }
+            pad.samples.push(KitSample {
+                path: library::to_portable(&sample_path),
+                zone: zone.clone(),
+            });
+        }
+    }
+
+    for (opcode, count) in unsupported_opcodes {
+        warnings.push(format!(
+            "unsupported opcode {opcode} is ignored ({count} region(s))"
+        ));
+    }
+
+    let kit = Kit {
+        name: path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        template: None,
+        pads: pads.into_values().collect(),
+    };
+
+    Ok((kit, warnings))
+}
+
+/// Exports a kit as an SFZ file, with one group per pad. The pads' velocity to volume mapping is
+/// written as an `amp_velcurve`.
+pub fn export(kit: &Kit, path: &Path) -> Result<(), String> {
+    let sfz_dir = path.parent().unwrap_or(Path::new(""));
+    let mut sfz = format!("// {}, exported from Nih Sampler\n", kit.name);
+
+    for pad in &kit.pads {
+        let _ = writeln!(sfz, "\n// {}", pad.name);
+        let _ = write!(sfz, "<group> key={}", pad.note);
+        if pad.max_volume > 0.0
+            && pad.min_volume != pad.max_volume
+            && pad.min_velocity < pad.max_velocity
+        {
+            let _ = write!(
+                sfz,
+                " amp_velcurve_{}={:.4} amp_velcurve_{}=1",
+                pad.min_velocity,
+                pad.min_volume / pad.max_volume,
+                pad.max_velocity
+            );
+        }
+        sfz.push('\n');
+
+        for sample in &pad.samples {
+            let zone = &sample.zone;
+            let sample_path = library::resolve(&sample.path);
+            let file_sample_rate =
+                sample_pool::file_sample_rate(&sample_path).unwrap_or(FALLBACK_SAMPLE_RATE);
+            let sample_path = sample_path
+                .strip_prefix(sfz_dir)
+                .unwrap_or(&sample_path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            let volume = if pad.max_volume > 0.0 {
+                zone.volume + util::gain_to_db(pad.max_volume)
+            } else {
+                -144.0
+            };
+
+            let _ = write!(
+                sfz,
+                "<region> sample={sample_path} lovel={} hivel={} volume={volume:.2}",
+                zone.min_velocity.max(pad.min_velocity),
+                zone.max_velocity.min(pad.max_velocity),
+            );
+            if let Some(hi_hat) = &pad.hi_hat {
+                if zone.pedal_chick {
+                    let _ = write!(
+                        sfz,
+                        " on_locc{cc}=0 on_hicc{cc}={}",
+                        hi_hat.close_threshold.saturating_sub(1),
+                        cc = hi_hat.cc
+                    );
+                } else if zone.min_openness > 0 || zone.max_openness < 127 {
+                    let _ = write!(
+                        sfz,
+                        " locc{cc}={} hicc{cc}={}",
+                        zone.min_openness,
+                        zone.max_openness,
+                        cc = hi_hat.cc
+                    );
+                }
+            }
+            if zone.pan != 0.0 {
+                let _ = write!(sfz, " pan={}", zone.pan);
+            }
+            if zone.tune != 0.0 {
+                let _ = write!(sfz, " tune={}", zone.tune.round());
+            }
+            if zone.reverse {
+                let _ = write!(sfz, " direction=reverse");
+            }
+            if zone.start > 0.0 {
+                let _ = write!(sfz, " offset={}", (zone.start * file_sample_rate) as u64);
+            }
+            if let Some(end) = zone.end {
+                let _ = write!(sfz, " end={}", (end * file_sample_rate) as u64);
+            }
+            if let (Some(loop_start), Some(loop_end)) = (zone.loop_start, zone.loop_end) {
+                let _ = write!(
+                    sfz,
+                    " loop_mode=loop_sustain loop_start={} loop_end={}",
+                    (loop_start * file_sample_rate) as u64,
+                    (loop_end * file_sample_rate) as u64
+                );
+            }
+            if let Some(envelope) = &zone.envelope {
+                let _ = write!(
+                    sfz,
+                    " ampeg_delay={} ampeg_attack={} ampeg_hold={} ampeg_decay={} \
+                     ampeg_sustain={} ampeg_release={}",
+                    envelope.delay,
+                    envelope.attack,
+                    envelope.hold,
+                    envelope.decay,
+                    envelope.sustain * 100.0,
+                    envelope.release
+                );
+            }
+            if zone.seq_length > 1 {
+                let _ = write!(
+                    sfz,
+                    " seq_length={} seq_position={}",
+                    zone.seq_length, zone.seq_position
+                );
+            }
+            if zone.lorand > 0.0 || zone.hirand < 1.0 {
+                let _ = write!(sfz, " lorand={} hirand={}", zone.lorand, zone.hirand);
+            }
+            if let Some(group) = zone.group {
+                let _ = write!(sfz, " group={group}");
+            }
+            if let Some(off_by) = zone.off_by {
+                let _ = write!(sfz, " off_by={off_by}");
+            }
+            sfz.push('\n');
+        }
+    }
+
+    std::fs::write(path, sfz).map_err(|e| e.to_string())
+}
+
+/// Parses the regions in an SFZ file, with the opcodes of the enclosing control, global, master and
+/// group headers merged in
+fn parse(contents: &str, warnings: &mut Vec<String>) -> Vec<HashMap<String, String>> {
+    let contents = apply_defines(&strip_comments(contents), warnings);
+
+    let mut control = HashMap::new();
+    let mut global = HashMap::new();
+    let mut master = HashMap::new();
+    let mut group = HashMap::new();
+    let mut regions = vec![];
+
+    let mut rest = contents.as_str();
+    while let Some(header_start) = rest.find('<') {
+        let Some(header_len) = rest[header_start..].find('>') else {
+            break;
+        };
+        let header = &rest[header_start + 1..header_start + header_len];
+        let body_start = header_start + header_len + 1;
+        let body_end = rest[body_start..]
+            .find('<')
+            .map_or(rest.len(), |index| body_start + index);
+        let opcodes = parse_opcodes(&rest[body_start..body_end]);
+
+        match header {
+            "control" => control = opcodes,
+            "global" => {
+                global = opcodes;
+                master.clear();
+                group.clear();
+            }
+            "master" => {
+                master = opcodes;
+                group.clear();
+            }
+            "group" => group = opcodes,
+            "region" => {
+                let mut region = control.clone();
+                for opcodes in [&global, &master, &group, &opcodes] {
+                    region.extend(opcodes.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+                regions.push(region);
+            }
+            header => warnings.push(format!("unsupported header <{header}> is ignored")),
+        }
+
+        rest = &rest[body_end..];
+    }
+
+    regions
+}
+
+/// Parses `name=value` pairs. Values can contain spaces (mostly sample paths), so a value runs
+/// until the next opcode.
+fn parse_opcodes(body: &str) -> HashMap<String, String> {
+    let bytes = body.as_bytes();
+    let mut opcode_starts = vec![];
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if i == 0 || bytes[i - 1].is_ascii_whitespace() {
+            let name_len = bytes[i..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                .count();
+            if name_len > 0 && bytes.get(i + name_len) == Some(&b'=') {
+                opcode_starts.push((i, i + name_len));
+                i += name_len + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    opcode_starts
+        .iter()
+        .enumerate()
+        .map(|(n, &(name_start, equals))| {
+            let value_end = opcode_starts
+                .get(n + 1)
+                .map_or(body.len(), |&(next_start, _)| next_start);
+            (
+                body[name_start..equals].to_string(),
+                body[equals + 1..value_end].trim().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn strip_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut rest = contents;
+    loop {
+        let line_comment = rest.find("//");
+        let block_comment = rest.find("/*");
+        match (line_comment, block_comment) {
+            (Some(line), block) if block.is_none_or(|block| line < block) => {
+                stripped.push_str(&rest[..line]);
+                rest = rest[line..]
+                    .find('\n')
+                    .map_or("", |end| &rest[line + end..]);
+            }
+            (_, Some(block)) => {
+                stripped.push_str(&rest[..block]);
+                // keep the comment as whitespace so the tokens around it stay separated
+                stripped.push(' ');
+                rest = rest[block..]
+                    .find("*/")
+                    .map_or("", |end| &rest[block + end + 2..]);
+            }
+            _ => {
+                stripped.push_str(rest);
+                return stripped;
+            }
+        }
+    }
+}
+
+/// Substitutes `#define $NAME value` variables. Includes aren't supported.
+fn apply_defines(contents: &str, warnings: &mut Vec<String>) -> String {
+    let mut defines = vec![];
+    let mut lines = vec![];
+    for line in contents.lines() {
+        let trimmed = line.trim_start();
+        if let Some(define) = trimmed.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.push((name.to_string(), value.to_string()));
+            }
+        } else if trimmed.starts_with("#include") {
+            warnings.push(format!("unsupported {trimmed} is ignored"));
+        } else {
+            lines.push(line);
+        }
+    }
+
+    // longer names first so `$KICK` doesn't replace part of `$KICK2`
+    defines.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+    let mut contents = lines.join("\n");
+    for (name, value) in defines {
+        contents = contents.replace(&name, &value);
+    }
+
+    contents
+}
+
+/// Parses a key given either as a note number or as a note name like `c#4`, where `c4` is 60
+/// The CC of one of the hi-hat pedal's opcodes, like `locc4` or `on_hicc4`
+fn pedal_cc(opcode: &str) -> Option<u8> {
+    let cc = ["locc", "hicc", "on_locc", "on_hicc"]
+        .iter()
+        .find_map(|prefix| opcode.strip_prefix(prefix))?;
+    cc.parse().ok().filter(|&cc| cc <= 127)
+}
+
+fn parse_key(key: &str) -> Option<u8> {
+    if let Ok(note) = key.parse::<u8>() {
+        return Some(note);
+    }
+
+    let key = key.to_lowercase();
+    let mut chars = key.chars();
+    let semitone = match chars.next()? {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        'b' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (semitone, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (semitone + 1, octave)
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        (semitone - 1, octave)
+    } else {
+        (semitone, rest)
+    };
+
+    let note = (octave.parse::<i32>().ok()? + 1) * 12 + semitone;
+    u8::try_from(note).ok().filter(|note| *note < 128)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// An empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nih-sampler-sfz-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn strips_line_and_block_comments() {
+        assert_eq!(strip_comments("a // c\nb /* x */c"), "a \nb  c");
+        assert_eq!(strip_comments("a /* b // c */ d"), "a   d");
+        // unterminated comments run to the end
+        assert_eq!(strip_comments("a /* b"), "a  ");
+    }
+
+    #[test]
+    fn expands_defines() {
+        let mut warnings = vec![];
+        let regions = parse(
+            "#define $KICK 36\n#define $KICK2 37\n#include \"other.sfz\"\n\
+             <region> sample=a.wav key=$KICK2\n<region> sample=b.wav key=$KICK",
+            &mut warnings,
+        );
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0]["key"], "37");
+        assert_eq!(regions[1]["key"], "36");
+        assert_eq!(warnings, ["unsupported #include \"other.sfz\" is ignored"]);
+    }
+
+    #[test]
+    fn parses_values_with_spaces() {
+        let opcodes = parse_opcodes(" sample=Kick Drum/kick 1.wav lovel=10  hivel=127\n");
+        assert_eq!(opcodes.len(), 3);
+        assert_eq!(opcodes["sample"], "Kick Drum/kick 1.wav");
+        assert_eq!(opcodes["lovel"], "10");
+        assert_eq!(opcodes["hivel"], "127");
+    }
+
+    #[test]
+    fn parses_note_names() {
+        assert_eq!(parse_key("36"), Some(36));
+        assert_eq!(parse_key("c4"), Some(60));
+        assert_eq!(parse_key("C#4"), Some(61));
+        assert_eq!(parse_key("db4"), Some(61));
+        assert_eq!(parse_key("a-1"), Some(9));
+        assert_eq!(parse_key("g9"), Some(127));
+        assert_eq!(parse_key("g#9"), None);
+        assert_eq!(parse_key("h4"), None);
+    }
+
+    #[test]
+    fn applies_default_path_from_control() {
+        let dir = test_dir("default-path");
+        let sfz_path = dir.join("kit.sfz");
+        std::fs::write(
+            &sfz_path,
+            "<control> default_path=Samples\\Kit\\\n<region> sample=kick 1.wav key=c2\n",
+        )
+        .unwrap();
+
+        let (kit, warnings) = import(&sfz_path).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(kit.pads.len(), 1);
+        assert_eq!(kit.pads[0].note, 36);
+        assert_eq!(
+            kit.pads[0].samples[0].path,
+            dir.join("Samples").join("Kit").join("kick 1.wav")
+        );
+    }
+
+    #[test]
+    fn loops_to_the_end_without_loop_end() {
+        let dir = test_dir("loop-end");
+        let sfz_path = dir.join("kit.sfz");
+        std::fs::write(
+            &sfz_path,
+            "<region> sample=a.wav key=36 loop_mode=loop_continuous loop_start=4410 end=22050\n\
+             <region> sample=b.wav key=37 loop_mode=loop_continuous\n",
+        )
+        .unwrap();
+
+        let (kit, warnings) = import(&sfz_path).unwrap();
+        // the files don't exist, so offsets are converted at the fallback sample rate and the
+        // length of the second one is unknown
+        assert_eq!(
+            warnings,
+            ["b.wav loops to the end of the file, which can't be read, so it doesn't loop"]
+        );
+        let zone = &kit.pads[0].samples[0].zone;
+        assert_eq!((zone.loop_start, zone.loop_end), (Some(0.1), Some(0.5)));
+        let zone = &kit.pads[1].samples[0].zone;
+        assert_eq!((zone.loop_start, zone.loop_end), (None, None));
+    }
+
+    #[test]
+    fn exported_kits_import_the_same() {
+        let dir = test_dir("round-trip");
+        let mut pad = Pad::new("Hat".into(), 42);
+        // a pedal on another CC than the conventional 4
+        let hi_hat = HiHat {
+            cc: 11,
+            close_threshold: 24,
+        };
+        pad.hi_hat = Some(hi_hat);
+        let zone = Zone {
+            min_velocity: 10,
+            max_velocity: 100,
+            volume: -3.0,
+            pan: 25.0,
+            tune: -50.0,
+            // the files don't exist, so offsets are converted at the fallback sample rate
+            start: 0.5,
+            end: Some(1.0),
+            loop_start: Some(0.25),
+            loop_end: Some(0.75),
+            envelope: Some(Envelope {
+                delay: 0.0,
+                attack: 0.25,
+                hold: 0.0,
+                decay: 0.5,
+                sustain: 0.5,
+                release: 0.125,
+            }),
+            seq_length: 2,
+            seq_position: 1,
+            hirand: 0.5,
+            group: Some(1),
+            off_by: Some(2),
+            reverse: true,
+            ..Zone::default()
+        };
+        let open = Zone {
+            min_openness: 64,
+            ..Zone::default()
+        };
+        let chick = Zone {
+            pedal_chick: true,
+            ..Zone::default()
+        };
+        for (file_name, zone) in [
+            ("hat 1.wav", &zone),
+            ("open.wav", &open),
+            ("chick.wav", &chick),
+        ] {
+            pad.samples.push(KitSample {
+                path: dir.join("Samples").join(file_name),
+                zone: zone.clone(),
+            });
+        }
+        let kit = Kit {
+            name: "Round trip".into(),
+            template: None,
+            pads: vec![pad],
+        };
+
+        let sfz_path = dir.join("kit.sfz");
+        export(&kit, &sfz_path).unwrap();
+        let (imported, warnings) = import(&sfz_path).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        assert_eq!(imported.name, "kit");
+        assert_eq!(imported.pads.len(), 1);
+        let pad = &imported.pads[0];
+        assert_eq!(pad.note, 42);
+        assert_eq!(pad.hi_hat, Some(hi_hat));
+        let paths: Vec<_> = pad.samples.iter().map(|sample| &sample.path).collect();
+        assert_eq!(
+            paths,
+            [
+                &dir.join("Samples").join("hat 1.wav"),
+                &dir.join("Samples").join("open.wav"),
+                &dir.join("Samples").join("chick.wav"),
+            ]
+        );
+        assert_eq!(pad.samples[0].zone, zone);
+        assert_eq!(pad.samples[1].zone, open);
+        assert_eq!(pad.samples[2].zone, chick);
+    }
+}
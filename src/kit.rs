@@ -0,0 +1,415 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    modulation::{ModRoute, MAX_CUTOFF},
+    poly::Mpe,
+    routing::Routing,
+    sample_report::SampleReport,
+    NihSamplerParams,
+};
+
+/// A kit file, stored as TOML (or JSON when the file has a `.json` extension) so it can be edited
+/// by hand and shared between projects. Every instance of the plugin plays a single pad, so when
+/// loading a kit an instance picks the pad matching its trigger note.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Kit {
+    #[serde(default)]
+    pub name: String,
+    /// Name of the template the pads' notes follow, used when remapping the kit to another one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, rename = "pad")]
+    pub pads: Vec<Pad>,
+}
+
+/// The configuration of a single instance
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pad {
+    #[serde(default)]
+    pub name: String,
+    pub note: u8,
+    #[serde(default)]
+    pub min_velocity: u8,
+    #[serde(default = "default_max_velocity")]
+    pub max_velocity: u8,
+    /// Linear gain at the minimum velocity
+    #[serde(default = "default_volume")]
+    pub min_volume: f32,
+    /// Linear gain at the maximum velocity
+    #[serde(default = "default_volume")]
+    pub max_volume: f32,
+    /// Relative paths are relative to the kit file
+    #[serde(default)]
+    pub samples: Vec<KitSample>,
+    /// Folders that are scanned for samples when the kit is loaded
+    #[serde(default)]
+    pub folders: Vec<PathBuf>,
+    /// Makes the pad a hi-hat, where samples are picked by the pedal position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hi_hat: Option<HiHat>,
+    /// Poly pressure between 0 and 1 at which the voices of the pad are choked, like grabbing a
+    /// cymbal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub choke_pressure: Option<f32>,
+    /// Linear gain applied to every voice
+    #[serde(default = "default_volume")]
+    pub gain: f32,
+    #[serde(default)]
+    pub pan: f32,
+    /// In semitones
+    #[serde(default)]
+    pub pitch: f32,
+    /// Of the voice filter, in Hz
+    #[serde(default = "default_cutoff")]
+    pub cutoff: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mod_routes: Vec<ModRoute>,
+    #[serde(default)]
+    pub routing: Routing,
+    /// Semitones the notes passed on to the output are moved by
+    #[serde(default)]
+    pub output_transpose: i8,
+    #[serde(default)]
+    pub sample_report: SampleReport,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mpe: Option<Mpe>,
+    /// MIDI CCs controlling parameters, by parameter id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cc_mappings: BTreeMap<String, u8>,
+}
+
+/// Settings of a hi-hat pad. The pedal position is read from a CC, with 0 being fully closed, and
+/// picks the samples whose openness range contains it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HiHat {
+    pub cc: u8,
+    /// Closing the pedal below this position chokes the open hats that are still ringing, and
+    /// plays the pedal chick samples
+    pub close_threshold: u8,
+}
+
+impl Default for HiHat {
+    fn default() -> Self {
+        Self {
+            cc: 4,
+            close_threshold: 16,
+        }
+    }
+}
+
+/// A sample in a kit. Can also be written as just the path when it uses the default settings.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "KitSampleRepr")]
+pub struct KitSample {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub zone: Zone,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KitSampleRepr {
+    Path(PathBuf),
+    Sample {
+        path: PathBuf,
+        #[serde(flatten)]
+        zone: Zone,
+    },
+}
+
+impl From<KitSampleRepr> for KitSample {
+    fn from(repr: KitSampleRepr) -> Self {
+        match repr {
+            KitSampleRepr::Path(path) => Self {
+                path,
+                zone: Zone::default(),
+            },
+            KitSampleRepr::Sample { path, zone } => Self { path, zone },
+        }
+    }
+}
+
+/// Playback settings of a single sample, modelled after SFZ regions
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Zone {
+    pub min_velocity: u8,
+    pub max_velocity: u8,
+    /// Range of pedal positions the sample plays at on hi-hat pads
+    pub min_openness: u8,
+    pub max_openness: u8,
+    /// On hi-hat pads, the sample is played when the pedal closes instead of by notes
+    pub pedal_chick: bool,
+    /// Volume in dB
+    pub volume: f32,
+    /// -100 is hard left, 100 is hard right
+    pub pan: f32,
+    /// Tuning in cents
+    pub tune: f32,
+    /// Where playback starts in seconds
+    pub start: f32,
+    /// Where playback ends in seconds, the end of the sample if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<f32>,
+    /// While the note is held, playback loops between these positions in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_start: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_end: Option<f32>,
+    /// Without an envelope the sample plays as a one-shot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<Envelope>,
+    /// Round robin: the sample only plays on the `seq_position`th of every `seq_length` notes
+    pub seq_length: u32,
+    pub seq_position: u32,
+    /// The sample only plays when a random number between 0 and 1 falls in this range
+    pub lorand: f32,
+    pub hirand: f32,
+    /// Playing a sample in a group stops all voices that are `off_by` that group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub off_by: Option<u32>,
+    /// Relative chance of being picked among the samples that match a note, 0 never plays
+    pub weight: f32,
+    /// Plays the sample backwards. Start, end and loop positions count from the end of the file.
+    pub reverse: bool,
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Self {
+            min_velocity: 0,
+            max_velocity: 127,
+            min_openness: 0,
+            max_openness: 127,
+            pedal_chick: false,
+            volume: 0.0,
+            pan: 0.0,
+            tune: 0.0,
+            start: 0.0,
+            end: None,
+            loop_start: None,
+            loop_end: None,
+            envelope: None,
+            seq_length: 1,
+            seq_position: 1,
+            lorand: 0.0,
+            hirand: 1.0,
+            group: None,
+            off_by: None,
+            weight: 1.0,
+            reverse: false,
+        }
+    }
+}
+
+impl Zone {
+    /// Whether the sample should be considered for a note, given the pedal position on hi-hat pads,
+    /// the number of notes played so far and a random number between 0 and 1
+    pub fn matches(
+        &self,
+        velocity: u8,
+        openness: Option<u8>,
+        note_count: u32,
+        random: f32,
+    ) -> bool {
+        (self.min_velocity..=self.max_velocity).contains(&velocity)
+            && openness
+                .is_none_or(|openness| (self.min_openness..=self.max_openness).contains(&openness))
+            && note_count % self.seq_length.max(1) + 1 == self.seq_position
+            && random >= self.lorand
+            && (random < self.hirand || self.hirand >= 1.0)
+    }
+}
+
+/// Volume envelope, with all times in seconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Envelope {
+    pub delay: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub decay: f32,
+    /// Level between 0 and 1
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self {
+            delay: 0.0,
+            attack: 0.0,
+            hold: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.0,
+        }
+    }
+}
+
+impl Envelope {
+    /// The level `time` seconds after the note started
+    pub fn level(&self, time: f32) -> f32 {
+        let mut time = time - self.delay;
+        if time < 0.0 {
+            return 0.0;
+        }
+        if time < self.attack {
+            return time / self.attack;
+        }
+        time -= self.attack;
+        if time < self.hold {
+            return 1.0;
+        }
+        time -= self.hold;
+        if time < self.decay {
+            return 1.0 - (1.0 - self.sustain) * time / self.decay;
+        }
+
+        self.sustain
+    }
+
+    /// The level `time` seconds after the note was released at `release_level`, `None` once the
+    /// release is over
+    pub fn release_level(&self, time: f32, release_level: f32) -> Option<f32> {
+        (time < self.release).then(|| release_level * (1.0 - time / self.release))
+    }
+}
+
+fn default_max_velocity() -> u8 {
+    127
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_cutoff() -> f32 {
+    MAX_CUTOFF
+}
+
+impl Pad {
+    pub fn new(name: String, note: u8) -> Self {
+        Self {
+            name,
+            note,
+            min_velocity: 0,
+            max_velocity: default_max_velocity(),
+            min_volume: default_volume(),
+            max_volume: default_volume(),
+            samples: vec![],
+            folders: vec![],
+            hi_hat: None,
+            choke_pressure: None,
+            gain: default_volume(),
+            pan: 0.0,
+            pitch: 0.0,
+            cutoff: default_cutoff(),
+            mod_routes: vec![],
+            routing: Routing::default(),
+            output_transpose: 0,
+            sample_report: SampleReport::default(),
+            mpe: None,
+            cc_mappings: BTreeMap::new(),
+        }
+    }
+
+    /// Captures the current configuration of an instance
+    pub fn from_params(params: &NihSamplerParams) -> Self {
+        Self {
+            name: params.pad_name.lock().unwrap().clone(),
+            note: params.note.value() as u8,
+            min_velocity: params.min_velocity.value() as u8,
+            max_velocity: params.max_velocity.value() as u8,
+            min_volume: params.min_volume.value(),
+            max_volume: params.max_volume.value(),
+            samples: params.samples.lock().unwrap().clone(),
+            folders: params.sample_folders.lock().unwrap().clone(),
+            hi_hat: *params.hi_hat.lock().unwrap(),
+            choke_pressure: *params.choke_pressure.lock().unwrap(),
+            gain: params.gain.value(),
+            pan: params.pan.value(),
+            pitch: params.pitch.value(),
+            cutoff: params.cutoff.value(),
+            mod_routes: params.mod_routes.lock().unwrap().clone(),
+            routing: *params.routing.lock().unwrap(),
+            output_transpose: *params.output_transpose.lock().unwrap(),
+            sample_report: *params.sample_report.lock().unwrap(),
+            mpe: *params.mpe.lock().unwrap(),
+            cc_mappings: params
+                .cc_mappings
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(param_id, cc)| (param_id.clone(), *cc))
+                .collect(),
+        }
+    }
+}
+
+impl Kit {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut kit: Kit = if is_json(path) {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())?
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string())?
+        };
+
+        // paths are made absolute again so they don't depend on where the kit was loaded from
+        let kit_dir = path.parent().unwrap_or(Path::new(""));
+        for pad in &mut kit.pads {
+            let sample_paths = pad.samples.iter_mut().map(|sample| &mut sample.path);
+            for sample_path in sample_paths.chain(pad.folders.iter_mut()) {
+                if sample_path.is_relative() && !sample_path.to_string_lossy().starts_with('$') {
+                    *sample_path = kit_dir.join(&sample_path);
+                }
+            }
+        }
+
+        Ok(kit)
+    }
+
+    /// Saves the kit. Paths outside of the library roots but next to the kit file are stored
+    /// relative to it, so a kit can be checked in together with its samples.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut kit = self.clone();
+        let kit_dir = path.parent().unwrap_or(Path::new(""));
+        for pad in &mut kit.pads {
+            let sample_paths = pad.samples.iter_mut().map(|sample| &mut sample.path);
+            for sample_path in sample_paths.chain(pad.folders.iter_mut()) {
+                if let Ok(relative) = sample_path.strip_prefix(kit_dir) {
+                    *sample_path = relative.to_path_buf();
+                }
+            }
+        }
+
+        let contents = if is_json(path) {
+            serde_json::to_string_pretty(&kit).map_err(|e| e.to_string())?
+        } else {
+            toml::to_string_pretty(&kit).map_err(|e| e.to_string())?
+        };
+        std::fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    /// The pad an instance triggered by `note` should use, falling back to the first pad
+    pub fn pad_for_note(&self, note: u8) -> Option<&Pad> {
+        self.pads
+            .iter()
+            .find(|pad| pad.note == note)
+            .or(self.pads.first())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use nih_plug::prelude::*;
+
+use crate::{
+    kit::{Kit, KitSample, Pad, Zone},
+    library,
+    sample_loader::{PadState, SampleMap},
+    sample_pool, LoadedSample, NihSamplerParams,
+};
+
+/// The pad of a kit in the bank with all of its samples decoded, ready to be switched to by a
+/// program change without touching the disk
+pub struct PreloadedPad {
+    pub pad: Pad,
+    /// The samples by their path in the sample list, with their content hashes and zones. Samples
+    /// in the pad's folders are included.
+    pub samples: Vec<PreloadedSample>,
+    /// The same samples, for the loader to catch up with
+    pub sample_map: Arc<SampleMap>,
+    /// The samples with the instance's settings, ready to be swapped in by the audio thread
+    pub state: Arc<PadState>,
+}
+
+#[derive(Clone)]
+pub struct PreloadedSample {
+    pub path: PathBuf,
+    pub sample: Arc<LoadedSample>,
+    pub content_hash: u64,
+    pub zone: Zone,
+}
+
+/// A bank of preloaded pads by program number
+#[derive(Clone, Default)]
+pub struct Bank {
+    /// The trigger note the pads were picked for. The bank is stale once the note changes.
+    pub note: u8,
+    /// `None` for kits that couldn't be loaded
+    pub pads: Vec<Option<Arc<PreloadedPad>>>,
+}
+
+/// Loads the kits in the bank and decodes the samples of the pad each one has for the instance's
+/// trigger note. Runs on a background thread.
+pub fn preload(params: &NihSamplerParams, sample_rate: f32) -> Bank {
+    let note = params.note.value() as u8;
+    let kit_bank = params.kit_bank.lock().unwrap().clone();
+    // switching pads keeps the instance's settings, apart from the ones the loader takes from the
+    // kit
+    let instance_pad = Pad::from_params(params);
+
+    let pads = kit_bank
+        .iter()
+        .map(|kit_path| {
+            let kit = Kit::load(&library::resolve(kit_path))
+                .map_err(|e| nih_warn!("failed to load kit {kit_path:?}: {e}"))
+                .ok()?;
+            let mut pad = kit.pad_for_note(note)?.clone();
+            pad.folders = pad
+                .folders
+                .iter()
+                .map(|folder| library::to_portable(&library::resolve(folder)))
+                .collect();
+
+            let zones = pad
+                .samples
+                .iter()
+                .map(|sample| (sample.path.clone(), sample.zone.clone()));
+            let folder_files = pad.folders.iter().flat_map(|folder| {
+                sample_pool::supported_files_in(&library::resolve(folder))
+                    .into_iter()
+                    .map(|file_path| (file_path, Zone::default()))
+            });
+
+            let mut samples = vec![];
+            let mut seen = HashSet::new();
+            for (path, zone) in zones.chain(folder_files) {
+                let path = library::to_portable(&library::resolve(&path));
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+
+                match sample_pool::load(&library::resolve(&path), sample_rate) {
+                    Ok((sample, content_hash)) => samples.push(PreloadedSample {
+                        path,
+                        sample,
+                        content_hash,
+                        zone,
+                    }),
+                    Err(status) => {
+                        nih_warn!("{kit_path:?}: {path:?} can't be loaded: {}", status.label())
+                    }
+                }
+            }
+
+            let sample_map = samples
+                .iter()
+                .map(|preloaded| (preloaded.path.clone(), preloaded.sample.clone()))
+                .collect::<HashMap<_, _>>();
+            let state = pad_state(&pad, &samples, &sample_map, &instance_pad);
+            Some(Arc::new(PreloadedPad {
+                pad,
+                samples,
+                sample_map: Arc::new(sample_map),
+                state: Arc::new(state),
+            }))
+        })
+        .collect();
+
+    Bank { note, pads }
+}
+
+/// Merges the instance's current settings into the pads of a preloaded bank again, after they were
+/// changed. The samples are shared with the old bank. Runs on a background thread.
+pub fn update_settings(bank: &Bank, params: &NihSamplerParams) -> Bank {
+    let instance_pad = Pad::from_params(params);
+    let pads = bank
+        .pads
+        .iter()
+        .map(|preloaded| {
+            let preloaded = preloaded.as_ref()?;
+            let state = pad_state(
+                &preloaded.pad,
+                &preloaded.samples,
+                &preloaded.sample_map,
+                &instance_pad,
+            );
+            Some(Arc::new(PreloadedPad {
+                pad: preloaded.pad.clone(),
+                samples: preloaded.samples.clone(),
+                sample_map: preloaded.sample_map.clone(),
+                state: Arc::new(state),
+            }))
+        })
+        .collect();
+
+    Bank {
+        note: bank.note,
+        pads,
+    }
+}
+
+/// The state the audio thread switches to, with the kit pad's samples and the instance's settings
+fn pad_state(
+    pad: &Pad,
+    samples: &[PreloadedSample],
+    sample_map: &SampleMap,
+    instance_pad: &Pad,
+) -> PadState {
+    PadState::new(
+        &Pad {
+            samples: samples
+                .iter()
+                .map(|preloaded| KitSample {
+                    path: preloaded.path.clone(),
+                    zone: preloaded.zone.clone(),
+                })
+                .collect(),
+            hi_hat: pad.hi_hat,
+            choke_pressure: pad.choke_pressure,
+            ..instance_pad.clone()
+        },
+        sample_map,
+    )
+}
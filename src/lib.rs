@@ -1,20 +1,54 @@
-use crate::playing_sample::PlayingSample;
-use editor_vizia::visualizer::VisualizerData;
+use crate::{
+    embed::EmbeddedSample,
+    kit::{HiHat, KitSample, Pad, Zone},
+    kit_bank::{Bank, PreloadedPad},
+    midi_learn::{CcOverrides, LearnTarget, Learned},
+    modulation::{ModRoute, ModState, MAX_CUTOFF, MIN_CUTOFF},
+    playing_sample::PlayingSample,
+    poly::{
+        Expression, Mpe, VoiceControls, CUTOFF_POLY_MOD_ID, GAIN_POLY_MOD_ID, MAX_VOICES,
+        MPE_TIMBRE_CC, PAN_POLY_MOD_ID, PITCH_POLY_MOD_ID,
+    },
+    routing::Routing,
+    sample_loader::{PadSample, PadState, SampleLoader, SampleUpdate},
+    sample_pool::SampleStatus,
+    sample_report::SampleReport,
+};
+use editor_vizia::{visualizer::VisualizerData, waveform::WaveformData};
 use nih_plug_vizia::ViziaState;
 use rand::prelude::*;
 use rubato::Resampler;
 use std::{
     cell::RefCell,
-    collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use rtrb;
 
 use nih_plug::prelude::*;
+mod browser;
 mod editor_vizia;
+mod embed;
+mod formats;
+mod kit;
+mod kit_bank;
+mod library;
+mod midi_learn;
+mod modulation;
 mod playing_sample;
+mod poly;
+mod relink;
+mod routing;
+mod sample_loader;
+mod sample_pool;
+mod sample_report;
+mod templates;
+mod watcher;
 
 /// A loaded sample stored as a vec of samples in the form:
 /// [
@@ -23,10 +57,95 @@ mod playing_sample;
 /// ]
 pub struct LoadedSample(Vec<Vec<f32>>);
 
+impl LoadedSample {
+    /// Length in samples
+    pub fn len(&self) -> usize {
+        self.0.first().map_or(0, Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Linearly interpolated value at a fractional position. Mono samples are played on all
+    /// channels.
+    pub fn sample_at(&self, channel_index: usize, position: f64) -> f32 {
+        let Some(channel) = self.0.get(channel_index.min(self.0.len().saturating_sub(1))) else {
+            return 0.0;
+        };
+
+        let index = position as usize;
+        let t = (position - index as f64) as f32;
+        let a = channel.get(index).copied().unwrap_or(0.0);
+        let b = channel.get(index + 1).copied().unwrap_or(0.0);
+        a + (b - a) * t
+    }
+}
+
 #[derive(Clone)]
 pub enum ThreadMessage {
-    LoadSample(PathBuf),
+    /// Adds files to the sample list, or loads them again if they're already in it
+    LoadSamples(Vec<PathBuf>),
     RemoveSample(PathBuf),
+    /// Replaces the old paths in the sample list with the new ones
+    RelinkSamples(Vec<(PathBuf, PathBuf)>),
+    /// Loads all supported files in a folder and its subfolders. Remembered folders are scanned
+    /// again on every load, picking up files that were added since.
+    LoadFolder { path: PathBuf, remember: bool },
+    ForgetFolder(PathBuf),
+    /// Replaces the samples and folders with the ones from a kit's pad. The parameters are set by
+    /// the editor.
+    LoadPad(Pad),
+    /// Publishes the settings the audio thread reads to it after the editor changed them
+    UpdateSettings,
+    /// Captures the next note or CC for the target, or stops capturing
+    Learn(Option<LearnTarget>),
+    /// Loads the kits in the bank again after it changed
+    PreloadKitBank,
+    /// Plays a sample once at a velocity between 0 and 1, regardless of its velocity range and the
+    /// other conditions of its zone
+    Audition { path: Arc<Path>, velocity: f32 },
+    /// Plays a file from the sample browser that was decoded by the editor, stopping the previous
+    /// preview
+    Preview {
+        path: Arc<Path>,
+        sample: Arc<LoadedSample>,
+        velocity: f32,
+    },
+}
+
+/// Work that's too heavy for the audio thread
+pub enum Task {
+    UpdateEmbeddedSamples,
+    /// Decodes the samples of the kits in the bank at the given sample rate
+    PreloadKitBank { sample_rate: f32 },
+    /// Merges the instance's settings into the preloaded pads again after they changed
+    UpdateKitBankSettings,
+    /// Loads, removes or relinks samples as requested by the editor
+    ChangeSamples(ThreadMessage),
+    /// Swaps in the samples the file watcher decoded again
+    ApplyReloads,
+    /// Creates or removes the file watcher after the watch files setting changed
+    UpdateWatcher,
+    /// Brings the sample list in line with the pad from the kit bank the audio thread switched to
+    /// after a program change
+    SwitchPad {
+        preloaded: Arc<PreloadedPad>,
+        generation: u64,
+    },
+    /// Frees data the audio thread is done with
+    Drop(Garbage),
+}
+
+/// Data the audio thread is done with, freed on the background thread instead
+pub enum Garbage {
+    Pad(Arc<PadState>),
+    Bank(Bank),
+    /// A path from the editor, such as the file that was previewed before
+    Path(Arc<Path>),
+    /// A voice that finished playing. It may hold the last reference to the data of a sample that
+    /// was removed or reloaded while it played.
+    Voice(PlayingSample),
 }
 
 /// Main plugin struct
@@ -34,20 +153,84 @@ pub struct NihSampler {
     pub params: Arc<NihSamplerParams>,
     pub playing_samples: Vec<PlayingSample>,
     pub sample_rate: f32,
-    pub loaded_samples: HashMap<PathBuf, LoadedSample>,
+    /// Swapped for the latest state from the loader at the start of every block
+    pub pad: Arc<PadState>,
     pub consumer: RefCell<Option<rtrb::Consumer<ThreadMessage>>>,
     pub visualizer: Arc<VisualizerData>,
+    pub waveform: Arc<WaveformData>,
+    pub sample_status: Arc<Mutex<HashMap<PathBuf, SampleStatus>>>,
+    /// Number of notes played so far, used for round robins
+    note_count: u32,
+    /// Loads samples on the background thread. Only locked outside of the audio thread.
+    loader: Arc<Mutex<SampleLoader>>,
+    sample_update: Arc<Mutex<Option<SampleUpdate>>>,
+    /// Counts the switches to pads from the kit bank, updates from the loader that were made
+    /// before the latest one are skipped
+    generation: u64,
+    reloads_pending: Arc<AtomicBool>,
+    /// Set when the embedded samples need to be brought in line with the sample list
+    embedded_samples_dirty: bool,
+    embed_samples: bool,
+    watch_files: bool,
+    learning: Option<LearnTarget>,
+    learn_producer: Option<rtrb::Producer<Learned>>,
+    /// The latest values of the CCs that moved since they were last sent to the editor
+    moved_ccs: [Option<f32>; 128],
+    /// The mapped CCs that moved, applied to the parameters until the editor set them
+    cc_overrides: CcOverrides,
+    mod_state: ModState,
+    /// The latest expression on every channel in MPE mode, applied to notes when they start
+    mpe_channels: [Expression; 16],
+    /// The preloaded kits program changes switch between
+    kit_bank: Bank,
+    bank_consumer: Option<rtrb::Consumer<Bank>>,
+    kit_bank_dirty: bool,
+    kit_bank_settings_dirty: bool,
+    /// The trigger note the kit bank was last requested for
+    bank_note: u8,
+    /// The file of the last preview from the sample browser
+    last_preview: Option<Arc<Path>>,
+    /// Bits for the notes on every channel whose note on was consumed by the routing policy, so
+    /// their note offs are consumed as well
+    consumed_notes: [u128; 16],
 }
 
 impl Default for NihSampler {
     fn default() -> Self {
+        let params = Arc::new(NihSamplerParams::default());
+        let sample_status = Arc::new(Mutex::new(HashMap::new()));
+        let loader = SampleLoader::new(params.clone(), sample_status.clone());
+
         Self {
-            params: Arc::new(Default::default()),
+            params,
             playing_samples: vec![],
-            loaded_samples: HashMap::with_capacity(64),
+            pad: Arc::new(PadState::default()),
             consumer: RefCell::new(None),
             sample_rate: 44100.0,
             visualizer: Arc::new(VisualizerData::new()),
+            waveform: Arc::new(WaveformData::new(loader.current())),
+            sample_status,
+            note_count: 0,
+            sample_update: loader.update(),
+            generation: 0,
+            reloads_pending: loader.reloads_pending(),
+            loader: Arc::new(Mutex::new(loader)),
+            embedded_samples_dirty: false,
+            embed_samples: false,
+            watch_files: false,
+            learning: None,
+            learn_producer: None,
+            moved_ccs: [None; 128],
+            cc_overrides: CcOverrides::default(),
+            mod_state: ModState::default(),
+            mpe_channels: [Expression::default(); 16],
+            kit_bank: Bank::default(),
+            bank_consumer: None,
+            kit_bank_dirty: false,
+            kit_bank_settings_dirty: false,
+            bank_note: 0,
+            last_preview: None,
+            consumed_notes: [0; 16],
         }
     }
 }
@@ -57,8 +240,47 @@ impl Default for NihSampler {
 pub struct NihSamplerParams {
     #[persist = "editor-state"]
     editor_state: Arc<ViziaState>,
-    #[persist = "sample-list"]
-    sample_list: Mutex<Vec<PathBuf>>,
+    /// The sample list with the playback settings of every sample
+    #[persist = "samples"]
+    samples: Mutex<Vec<KitSample>>,
+    /// Audio data of the samples in `samples`, only filled when `embed_samples` is enabled
+    #[persist = "embedded-samples"]
+    embedded_samples: Mutex<HashMap<PathBuf, EmbeddedSample>>,
+    /// Content hashes of the samples when they were last loaded, used to find them when relinking
+    #[persist = "sample-hashes"]
+    sample_hashes: Mutex<HashMap<PathBuf, u64>>,
+    #[persist = "sample-folders"]
+    sample_folders: Mutex<Vec<PathBuf>>,
+    /// Name of the pad this instance plays, usually set from a template or kit
+    #[persist = "pad-name"]
+    pad_name: Mutex<String>,
+    /// MIDI CCs controlling parameters, by parameter id
+    #[persist = "cc-mappings"]
+    cc_mappings: Mutex<HashMap<String, u8>>,
+    /// The modulation matrix
+    #[persist = "mod-routes"]
+    mod_routes: Mutex<Vec<ModRoute>>,
+    /// Set when the pad is a hi-hat
+    #[persist = "hi-hat"]
+    hi_hat: Mutex<Option<HiHat>>,
+    /// Poly pressure that chokes the voices on its note, if enabled
+    #[persist = "choke-pressure"]
+    choke_pressure: Mutex<Option<f32>>,
+    /// MIDI output reporting which sample was picked
+    #[persist = "sample-report"]
+    sample_report: Mutex<SampleReport>,
+    /// Which incoming events are passed on to the output
+    #[persist = "routing"]
+    routing: Mutex<Routing>,
+    /// Semitones the notes passed on to the output are moved by
+    #[persist = "output-transpose"]
+    output_transpose: Mutex<i8>,
+    /// Set when MPE mode is on
+    #[persist = "mpe"]
+    mpe: Mutex<Option<Mpe>>,
+    /// Kit files switched to by program changes, in program order
+    #[persist = "kit-bank"]
+    kit_bank: Mutex<Vec<PathBuf>>,
 
     #[id = "note"]
     pub note: IntParam,
@@ -72,13 +294,43 @@ pub struct NihSamplerParams {
     pub min_volume: FloatParam,
     #[id = "max-volume"]
     pub max_volume: FloatParam,
+
+    /// Applied to every voice, and modulated per voice by hosts that support it
+    #[id = "gain"]
+    pub gain: FloatParam,
+    #[id = "pan"]
+    pub pan: FloatParam,
+    /// In semitones
+    #[id = "pitch"]
+    pub pitch: FloatParam,
+    #[id = "cutoff"]
+    pub cutoff: FloatParam,
+
+    #[id = "embed-samples"]
+    pub embed_samples: BoolParam,
+    /// Reload samples when their files change on disk
+    #[id = "watch-files"]
+    pub watch_files: BoolParam,
 }
 
 impl Default for NihSamplerParams {
     fn default() -> Self {
         Self {
             editor_state: ViziaState::new(|| (400, 700)),
-            sample_list: Mutex::new(vec![]),
+            samples: Mutex::new(vec![]),
+            embedded_samples: Mutex::new(HashMap::new()),
+            sample_hashes: Mutex::new(HashMap::new()),
+            sample_folders: Mutex::new(vec![]),
+            pad_name: Mutex::new(String::new()),
+            cc_mappings: Mutex::new(HashMap::new()),
+            mod_routes: Mutex::new(vec![]),
+            hi_hat: Mutex::new(None),
+            choke_pressure: Mutex::new(None),
+            sample_report: Mutex::new(SampleReport::Off),
+            routing: Mutex::new(Routing::default()),
+            output_transpose: Mutex::new(0),
+            mpe: Mutex::new(None),
+            kit_bank: Mutex::new(vec![]),
             note: IntParam::new("Note", 40, IntRange::Linear { min: 0, max: 127 }),
             min_velocity: IntParam::new("Min velocity", 0, IntRange::Linear { min: 0, max: 127 }),
             max_velocity: IntParam::new("Max velocity", 127, IntRange::Linear { min: 0, max: 127 }),
@@ -98,6 +350,118 @@ impl Default for NihSamplerParams {
             .with_unit(" dB")
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            gain: FloatParam::new(
+                "Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_poly_modulation_id(GAIN_POLY_MOD_ID)
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            pan: FloatParam::new(
+                "Pan",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_poly_modulation_id(PAN_POLY_MOD_ID)
+            .with_value_to_string(formatters::v2s_f32_panning())
+            .with_string_to_value(formatters::s2v_f32_panning()),
+            pitch: FloatParam::new(
+                "Pitch",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_poly_modulation_id(PITCH_POLY_MOD_ID)
+            .with_unit(" st")
+            .with_step_size(0.01),
+            cutoff: FloatParam::new(
+                "Cutoff",
+                MAX_CUTOFF,
+                FloatRange::Skewed {
+                    min: MIN_CUTOFF,
+                    max: MAX_CUTOFF,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_poly_modulation_id(CUTOFF_POLY_MOD_ID)
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            embed_samples: BoolParam::new("Embed samples", false).non_automatable(),
+            watch_files: BoolParam::new("Watch files", false).non_automatable(),
+        }
+    }
+}
+
+impl NihSamplerParams {
+    /// The paths in the sample list, in its order
+    fn sample_paths(&self) -> Vec<PathBuf> {
+        let samples = self.samples.lock().unwrap();
+        samples.iter().map(|sample| sample.path.clone()).collect()
+    }
+
+    fn has_sample(&self, path: &Path) -> bool {
+        let samples = self.samples.lock().unwrap();
+        samples.iter().any(|sample| sample.path == path)
+    }
+
+    /// The playback settings of a sample in the list, or the defaults if it isn't in there
+    fn sample_zone(&self, path: &Path) -> Zone {
+        let samples = self.samples.lock().unwrap();
+        samples
+            .iter()
+            .find(|sample| sample.path == path)
+            .map(|sample| sample.zone.clone())
+            .unwrap_or_default()
+    }
+
+    /// The poly modulated parameters for a voice, with the mapped CCs, the host's offsets for the
+    /// voice and its note expressions applied
+    fn voice_controls(
+        &self,
+        playing_sample: &PlayingSample,
+        cc_overrides: &CcOverrides,
+        cc_mappings: &BTreeMap<String, u8>,
+    ) -> VoiceControls {
+        let value = |id: &str, param: &FloatParam, poly_modulation_id: u32| {
+            let offset = playing_sample.poly_offsets[poly_modulation_id as usize];
+            param.preview_plain(cc_overrides.normalized(cc_mappings, id, param) + offset)
+        };
+
+        VoiceControls::new(
+            value("gain", &self.gain, GAIN_POLY_MOD_ID),
+            value("pan", &self.pan, PAN_POLY_MOD_ID),
+            value("pitch", &self.pitch, PITCH_POLY_MOD_ID),
+            value("cutoff", &self.cutoff, CUTOFF_POLY_MOD_ID),
+            &playing_sample.expression.combine(&playing_sample.mpe),
+        )
+    }
+
+    /// Whether the parameter with this id was set to a normalized value, as far as its steps
+    /// allow. Parameters the audio thread doesn't read count as set.
+    fn is_set_to(&self, id: &str, value: f32) -> bool {
+        fn is_set_to<P: Param>(param: &P, value: f32) -> bool {
+            let snapped = param.preview_normalized(param.preview_plain(value));
+            (param.unmodulated_normalized_value() - snapped).abs() < 1e-4
+        }
+
+        match id {
+            "note" => is_set_to(&self.note, value),
+            "min-velocity" => is_set_to(&self.min_velocity, value),
+            "max-velocity" => is_set_to(&self.max_velocity, value),
+            "min-volume" => is_set_to(&self.min_volume, value),
+            "max-volume" => is_set_to(&self.max_volume, value),
+            "gain" => is_set_to(&self.gain, value),
+            "pan" => is_set_to(&self.pan, value),
+            "pitch" => is_set_to(&self.pitch, value),
+            "cutoff" => is_set_to(&self.cutoff, value),
+            _ => true,
         }
     }
 }
@@ -109,11 +473,11 @@ impl Plugin for NihSampler {
     const EMAIL: &'static str = "info@example.com";
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
-    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
-    const MIDI_OUTPUT: MidiConfig = MidiConfig::Basic;
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+    const MIDI_OUTPUT: MidiConfig = MidiConfig::MidiCCs;
 
     type SysExMessage = ();
-    type BackgroundTask = ();
+    type BackgroundTask = Task;
 
     const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
         main_input_channels: None,
@@ -125,15 +489,79 @@ impl Plugin for NihSampler {
         self.params.clone()
     }
 
+    fn filter_state(state: &mut PluginState) {
+        // older states kept the sample list and the settings of the samples under separate keys
+        let Some(sample_list) = state.fields.remove("sample-list") else {
+            return;
+        };
+        let sample_zones = state.fields.remove("sample-zones");
+        if state.fields.contains_key("samples") {
+            return;
+        }
+
+        let sample_list: Vec<PathBuf> = serde_json::from_str(&sample_list).unwrap_or_default();
+        let mut sample_zones: HashMap<PathBuf, Zone> = sample_zones
+            .and_then(|sample_zones| serde_json::from_str(&sample_zones).ok())
+            .unwrap_or_default();
+        let samples: Vec<KitSample> = sample_list
+            .into_iter()
+            .map(|path| KitSample {
+                zone: sample_zones.remove(&path).unwrap_or_default(),
+                path,
+            })
+            .collect();
+        if let Ok(samples) = serde_json::to_string(&samples) {
+            state.fields.insert(String::from("samples"), samples);
+        }
+    }
+
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let params = self.params.clone();
+        let loader = self.loader.clone();
+        let (bank_producer, bank_consumer) = rtrb::RingBuffer::new(4);
+        self.bank_consumer = Some(bank_consumer);
+        let bank_producer = Mutex::new(bank_producer);
+        // the last bank sent to the audio thread, to merge changed settings into
+        let preloaded_bank = Mutex::new(Bank::default());
+        Box::new(move |task| match task {
+            Task::UpdateEmbeddedSamples => embed::update_embedded_samples(&params),
+            Task::PreloadKitBank { sample_rate } => {
+                let bank = kit_bank::preload(&params, sample_rate);
+                *preloaded_bank.lock().unwrap() = bank.clone();
+                let _ = bank_producer.lock().unwrap().push(bank);
+            }
+            Task::UpdateKitBankSettings => {
+                let mut preloaded_bank = preloaded_bank.lock().unwrap();
+                *preloaded_bank = kit_bank::update_settings(&preloaded_bank, &params);
+                let _ = bank_producer.lock().unwrap().push(preloaded_bank.clone());
+            }
+            Task::ChangeSamples(message) => loader.lock().unwrap().handle(message),
+            Task::ApplyReloads => loader.lock().unwrap().apply_reloads(),
+            Task::UpdateWatcher => loader.lock().unwrap().update_watcher(),
+            Task::SwitchPad {
+                preloaded,
+                generation,
+            } => loader.lock().unwrap().switch_pad(&preloaded, generation),
+            Task::Drop(garbage) => drop(garbage),
+        })
+    }
+
     fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
-        let (producer, consumer) = rtrb::RingBuffer::new(10);
+        // every file dropped onto the editor is sent on its own, so there's room for big drops
+        let (producer, consumer) = rtrb::RingBuffer::new(256);
         self.consumer.replace(Some(consumer));
+        // the moved CCs are sent once per block, with room for all of them and the learned events
+        let (learn_producer, learn_consumer) = rtrb::RingBuffer::new(256);
+        self.learn_producer = Some(learn_producer);
 
         editor_vizia::create(
             self.params.clone(),
             self.params.editor_state.clone(),
             Arc::new(Mutex::new(producer)),
             Arc::clone(&self.visualizer),
+            Arc::clone(&self.waveform),
+            Arc::clone(&self.sample_status),
+            Arc::new(Mutex::new(learn_consumer)),
         )
     }
 
@@ -141,15 +569,30 @@ impl Plugin for NihSampler {
         &mut self,
         _audio_io_layout: &AudioIOLayout,
         buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         nih_log!("changed sample rate to {}", buffer_config.sample_rate);
 
         self.sample_rate = buffer_config.sample_rate;
+        // voices are stolen rather than pushed past the limit, so they're never reallocated
+        self.playing_samples.reserve(MAX_VOICES);
+        self.waveform
+            .sample_rate
+            .store(self.sample_rate, Ordering::Relaxed);
+
+        // the samples are picked up at the start of the first block
+        self.loader.lock().unwrap().reload_all(self.sample_rate);
+        self.watch_files = self.params.watch_files.value();
 
-        let sample_list = self.params.sample_list.lock().unwrap().clone();
-        for path in sample_list {
-            self.load_sample(path.clone());
+        self.embed_samples = self.params.embed_samples.value();
+        if self.embed_samples {
+            context.execute_background(Task::UpdateEmbeddedSamples);
+        }
+        self.bank_note = self.params.note.value() as u8;
+        if !self.params.kit_bank.lock().unwrap().is_empty() {
+            context.execute_background(Task::PreloadKitBank {
+                sample_rate: self.sample_rate,
+            });
         }
 
         return true;
@@ -161,44 +604,122 @@ impl Plugin for NihSampler {
         _aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        self.proess_messages();
+        self.proess_messages(context);
         self.process_midi(context, buffer);
+        self.send_moved_ccs();
+        // the parameters take over from the CCs again once the editor set them
+        let editor_open = self.params.editor_state.is_open();
+        self.cc_overrides
+            .release(&self.pad.cc_mappings, |id, value| {
+                editor_open && self.params.is_set_to(id, value)
+            });
+
+        if self.embed_samples != self.params.embed_samples.value() {
+            self.embed_samples = self.params.embed_samples.value();
+            self.embedded_samples_dirty = true;
+        }
+        if self.embedded_samples_dirty {
+            self.embedded_samples_dirty = false;
+            context.execute_background(Task::UpdateEmbeddedSamples);
+        }
+        if self.watch_files != self.params.watch_files.value() {
+            self.watch_files = self.params.watch_files.value();
+            context.execute_background(Task::UpdateWatcher);
+        }
+        // the bank holds the pads for the trigger note, so it's loaded again when that changes
+        if self.bank_note != self.params.note.value() as u8 {
+            self.bank_note = self.params.note.value() as u8;
+            self.kit_bank_dirty = true;
+        }
+        if self.kit_bank_dirty {
+            self.kit_bank_dirty = false;
+            // preloading picks up the current settings as well
+            self.kit_bank_settings_dirty = false;
+            context.execute_background(Task::PreloadKitBank {
+                sample_rate: self.sample_rate,
+            });
+        }
+        // a program change keeps the instance's settings, so the bank's pads are kept up to date
+        // with them
+        if self.kit_bank_settings_dirty {
+            self.kit_bank_settings_dirty = false;
+            context.execute_background(Task::UpdateKitBankSettings);
+        }
 
         let mut amplitude = 0.0;
 
+        // the poly modulated parameters are picked up once per block
         for playing_sample in &mut self.playing_samples {
-            // attempt to get sample data
-            if let Some(loaded_sample) = self.loaded_samples.get(&playing_sample.handle) {
-                // channel_samples is [a, b, c]
-                for channel_samples in buffer.iter_samples() {
-                    // if sample isnt in the future
-                    if playing_sample.position >= 0 {
-                        for (channel_index, sample) in channel_samples.into_iter().enumerate() {
-                            let s = loaded_sample
-                                .0
-                                .get(channel_index)
-                                .unwrap_or(&vec![])
-                                .get(playing_sample.position as usize)
-                                .unwrap_or(&0.0)
-                                * playing_sample.gain;
-                            *sample += s;
-                            amplitude += s.abs();
-                        }
-                    }
-                    playing_sample.position += 1;
+            playing_sample.set_controls(self.params.voice_controls(
+                playing_sample,
+                &self.cc_overrides,
+                &self.pad.cc_mappings,
+            ));
+        }
+
+        // voices keep their own reference to the sample data, so samples that get reloaded or
+        // removed while playing ring out with the data they started with
+        let channels = buffer.channels();
+        for playing_sample in &mut self.playing_samples {
+            // channel_samples is [a, b, c]
+            for (sample_index, channel_samples) in buffer.iter_samples().enumerate() {
+                if playing_sample
+                    .release_at
+                    .is_some_and(|release_at| release_at <= sample_index)
+                {
+                    playing_sample.release();
                 }
+                // if sample isnt in the future
+                if playing_sample.delay > 0 {
+                    playing_sample.delay -= 1;
+                    continue;
+                }
+                if playing_sample.is_done() {
+                    break;
+                }
+
+                for (channel_index, sample) in channel_samples.into_iter().enumerate() {
+                    let value = playing_sample.value(channel_index);
+                    let s = playing_sample.filter(channel_index, value)
+                        * playing_sample.channel_gain(channel_index, channels);
+                    *sample += s;
+                    amplitude += s.abs();
+                }
+                playing_sample.advance();
             }
         }
 
         amplitude /= buffer.samples() as f32 * buffer.channels() as f32;
         self.visualizer.store(amplitude);
+        let sample_rate = self.sample_rate as f64;
+        let playheads = self
+            .playing_samples
+            .iter()
+            .filter(|e| !e.is_done())
+            .map(|e| (&*e.handle, (e.position / sample_rate) as f32));
+        self.waveform.store_playheads(playheads);
 
-        // remove samples that are done playing
-        self.playing_samples
-            .retain(|e| match self.loaded_samples.get(&e.handle) {
-                Some(sample) => e.position < sample.0[0].len() as isize,
-                None => false,
-            });
+        // remove samples that are done playing, letting the host know so it stops modulating the
+        // voices it started
+        let timing = buffer.samples().saturating_sub(1) as u32;
+        let mut index = 0;
+        while index < self.playing_samples.len() {
+            if !self.playing_samples[index].is_done() {
+                index += 1;
+                continue;
+            }
+            // removed in place so the oldest voices stay in front
+            let playing_sample = self.playing_samples.remove(index);
+            if playing_sample.from_note_on {
+                context.send_event(NoteEvent::VoiceTerminated {
+                    timing,
+                    voice_id: playing_sample.voice_id,
+                    channel: playing_sample.channel,
+                    note: playing_sample.note,
+                });
+            }
+            context.execute_background(Task::Drop(Garbage::Voice(playing_sample)));
+        }
 
         ProcessStatus::Normal
     }
@@ -255,71 +776,414 @@ fn resample(samples: LoadedSample, sample_rate_in: f32, sample_rate_out: f32) ->
 }
 
 impl NihSampler {
+    /// The plain value of a parameter, following the CC mapped to it
+    fn param_value<P: Param>(&self, id: &str, param: &P) -> P::Plain {
+        self.cc_overrides.plain(&self.pad.cc_mappings, id, param)
+    }
+
     fn velocity_to_gain(&self, velocity: u8) -> f32 {
+        let min_volume = self.param_value("min-volume", &self.params.min_volume);
+        let max_volume = self.param_value("max-volume", &self.params.max_volume);
+        let min_velocity = self.param_value("min-velocity", &self.params.min_velocity);
+        let max_velocity = self.param_value("max-velocity", &self.params.max_velocity);
+
+        // auditions and previews don't go through the velocity range, so velocities outside of it
+        // get the volume at its nearest end
+        if max_velocity <= min_velocity {
+            return if (velocity as i32) < min_velocity {
+                min_volume
+            } else {
+                max_volume
+            };
+        }
+
         // this is just mapping from the velocity range to volume range
-        self.params.min_volume.value()
-            + (self.params.max_volume.value() - self.params.min_volume.value())
-                * (velocity - self.params.min_velocity.value() as u8) as f32
-                / (self.params.max_velocity.value() - self.params.min_velocity.value()) as f32
+        let position = ((velocity as f32 - min_velocity as f32)
+            / (max_velocity - min_velocity) as f32)
+            .clamp(0.0, 1.0);
+        min_volume + (max_volume - min_volume) * position
+    }
+
+    /// Evaluates the modulation of the playing voices again after a controller moved
+    fn update_modulation(&mut self) {
+        let mod_routes = &self.pad.mod_routes;
+        if mod_routes.iter().all(|route| route.source.is_per_note()) {
+            return;
+        }
+
+        for playing_sample in &mut self.playing_samples {
+            playing_sample.set_modulation(self.mod_state.evaluate(
+                mod_routes,
+                playing_sample.velocity,
+                playing_sample.random,
+                playing_sample.pressure,
+            ));
+        }
+    }
+
+    /// Sends the CCs that moved to the editor, which sets the parameters mapped to them through
+    /// the host. CCs that don't fit in the ring buffer are sent in the next block, and the ones
+    /// that moved while the editor is closed are sent when it opens.
+    fn send_moved_ccs(&mut self) {
+        let Some(learn_producer) = &mut self.learn_producer else {
+            return;
+        };
+        if !self.params.editor_state.is_open() {
+            return;
+        }
+
+        for (cc, moved) in self.moved_ccs.iter_mut().enumerate() {
+            let Some(value) = *moved else {
+                continue;
+            };
+            if learn_producer
+                .push(Learned::CcMoved {
+                    cc: cc as u8,
+                    value,
+                })
+                .is_err()
+            {
+                return;
+            }
+            *moved = None;
+        }
     }
 
-    fn proess_messages(&mut self) {
+    fn proess_messages(&mut self, context: &mut impl ProcessContext<Self>) {
         let mut consumer = self.consumer.take();
         if let Some(consumer) = &mut consumer {
             while let Ok(message) = consumer.pop() {
                 match message {
-                    ThreadMessage::LoadSample(path) => {
-                        self.load_sample(path);
+                    ThreadMessage::Learn(target) => {
+                        self.learning = target;
+                    }
+                    ThreadMessage::PreloadKitBank => {
+                        self.kit_bank_dirty = true;
+                    }
+                    ThreadMessage::Audition { path, velocity } => {
+                        self.audition(context, &path, velocity);
+                        context.execute_background(Task::Drop(Garbage::Path(path)));
                     }
-                    ThreadMessage::RemoveSample(path) => {
-                        self.remove_sample(path);
+                    ThreadMessage::Preview {
+                        path,
+                        sample,
+                        velocity,
+                    } => {
+                        self.preview(context, path, sample, velocity);
+                    }
+                    // the editor changed the settings, which the loader hands back with the samples
+                    ThreadMessage::UpdateSettings => {
+                        context.execute_background(Task::ChangeSamples(message));
+                        self.kit_bank_settings_dirty = true;
+                    }
+                    // the other messages change the samples, which is done in the background. A
+                    // pad from a kit changes the settings as well.
+                    message => {
+                        self.kit_bank_settings_dirty |=
+                            matches!(message, ThreadMessage::LoadPad(_));
+                        context.execute_background(Task::ChangeSamples(message));
+                        self.embedded_samples_dirty = self.embed_samples;
                     }
                 }
             }
         }
 
         self.consumer.replace(consumer);
+
+        if let Some(bank_consumer) = &mut self.bank_consumer {
+            while let Ok(bank) = bank_consumer.pop() {
+                let bank = std::mem::replace(&mut self.kit_bank, bank);
+                context.execute_background(Task::Drop(Garbage::Bank(bank)));
+            }
+        }
+
+        if self.reloads_pending.swap(false, Ordering::Relaxed) {
+            context.execute_background(Task::ApplyReloads);
+            self.embedded_samples_dirty = self.embed_samples;
+        }
+
+        // the loader is never waited for, if it's busy publishing the samples are picked up in the
+        // next block instead
+        let update = self
+            .sample_update
+            .try_lock()
+            .ok()
+            .and_then(|mut update| update.take());
+        if let Some(update) = update {
+            let pad = if update.generation == self.generation {
+                std::mem::replace(&mut self.pad, update.pad)
+            } else {
+                update.pad
+            };
+            context.execute_background(Task::Drop(Garbage::Pad(pad)));
+        }
     }
 
     fn process_midi(&mut self, context: &mut impl ProcessContext<Self>, buffer: &mut Buffer) {
         let mut next_event = context.next_event();
         let start_sample = context.transport().pos_samples().unwrap_or_default();
+        let mpe = self.pad.mpe;
 
         for (sample_id, _channel_samples) in buffer.iter_samples().enumerate() {
             while let Some(event) = next_event {
                 if event.timing() > sample_id as u32 {
                     break;
                 }
+                let trigger_note = self.param_value("note", &self.params.note) as u8;
+                let min_velocity = self.param_value("min-velocity", &self.params.min_velocity);
+                let max_velocity = self.param_value("max-velocity", &self.params.max_velocity);
                 match event {
-                    NoteEvent::NoteOn { note, velocity, .. }
-                        if note == self.params.note.value() as u8
-                            && (velocity * 127.0) as u8
-                                >= self.params.min_velocity.value() as u8
-                            && (velocity * 127.0) as u8
-                                <= self.params.max_velocity.value() as u8 =>
+                    NoteEvent::NoteOn { note, .. } if self.learning == Some(LearnTarget::Note) => {
+                        self.learning = None;
+                        if let Some(learn_producer) = &mut self.learn_producer {
+                            let _ = learn_producer.push(Learned::Note(note));
+                        }
+                    }
+                    NoteEvent::MidiPitchBend { channel, .. }
+                    | NoteEvent::MidiChannelPressure { channel, .. }
+                    | NoteEvent::MidiCC {
+                        channel,
+                        cc: MPE_TIMBRE_CC,
+                        ..
+                    } if mpe.is_some_and(|mpe| mpe.is_member_channel(channel)) => {
+                        if let Some(mpe) = &mpe {
+                            self.apply_mpe(mpe, channel, &event);
+                        }
+                        self.forward(context, event);
+                    }
+                    NoteEvent::MidiCC { cc, value, .. } => {
+                        let previous = self.mod_state.cc[cc as usize & 127];
+                        self.mod_state.cc[cc as usize & 127] = value;
+                        self.update_modulation();
+
+                        if let Some(hi_hat) = self.pad.hi_hat.filter(|hi_hat| hi_hat.cc == cc) {
+                            let threshold = hi_hat.close_threshold as f32 / 127.0;
+                            if previous >= threshold && value < threshold {
+                                for playing_sample in &mut self.playing_samples {
+                                    if playing_sample.open_hat {
+                                        playing_sample.choke();
+                                    }
+                                }
+                                // the harder the pedal is closed, the louder the chick, kept
+                                // within the pad's velocity range like a played note
+                                let velocity = ((previous - value) * 127.0)
+                                    .round()
+                                    .max(min_velocity as f32)
+                                    .min(max_velocity as f32)
+                                    / 127.0;
+                                if let Some(index) = self.trigger(
+                                    context,
+                                    trigger_note,
+                                    velocity,
+                                    event.timing(),
+                                    start_sample,
+                                    true,
+                                ) {
+                                    self.pad.sample_report.send(
+                                        context,
+                                        event.timing(),
+                                        index,
+                                        velocity,
+                                    );
+                                }
+                            }
+                        }
+
+                        if let Some(LearnTarget::Param(param)) = self.learning {
+                            self.learning = None;
+                            if let Some(learn_producer) = &mut self.learn_producer {
+                                let _ = learn_producer.push(Learned::Cc { param, cc });
+                            }
+                        }
+                        // the editor sets the mapped parameters, including the one just learned
+                        self.moved_ccs[cc as usize & 127] = Some(value);
+                        self.cc_overrides.set(&self.pad.cc_mappings, cc, value);
+                        self.forward(context, event);
+                    }
+                    NoteEvent::MidiPitchBend { value, .. } => {
+                        self.mod_state.pitch_bend = (value - 0.5) * 2.0;
+                        self.update_modulation();
+                        self.forward(context, event);
+                    }
+                    NoteEvent::MidiChannelPressure { pressure, .. } => {
+                        self.mod_state.aftertouch = pressure;
+                        self.update_modulation();
+                        self.forward(context, event);
+                    }
+                    NoteEvent::PolyPressure {
+                        voice_id,
+                        channel,
+                        note,
+                        pressure,
+                        ..
+                    } => {
+                        // only the voices held by the pressed note, like note offs
+                        let choke_pressure = self.pad.choke_pressure;
+                        for playing_sample in &mut self.playing_samples {
+                            if playing_sample.from_note_on
+                                && playing_sample.is_voice(voice_id, channel, note)
+                            {
+                                playing_sample.pressure = pressure;
+                                if choke_pressure.is_some_and(|choke| pressure >= choke) {
+                                    playing_sample.choke();
+                                }
+                            }
+                        }
+                        self.update_modulation();
+                        self.forward(context, event);
+                    }
+                    NoteEvent::MidiProgramChange { program, .. } => {
+                        // a bank that was loaded for another trigger note has the wrong pads
+                        let preloaded = (self.kit_bank.note == trigger_note)
+                            .then(|| self.kit_bank.pads.get(program as usize).cloned().flatten())
+                            .flatten();
+                        if let Some(preloaded) = preloaded {
+                            // the samples are switched right away, the loader catches up in the
+                            // background
+                            let pad = std::mem::replace(&mut self.pad, preloaded.state.clone());
+                            context.execute_background(Task::Drop(Garbage::Pad(pad)));
+                            self.generation += 1;
+                            context.execute_background(Task::SwitchPad {
+                                preloaded,
+                                generation: self.generation,
+                            });
+                            self.embedded_samples_dirty = self.embed_samples;
+                        }
+                        self.forward(context, event);
+                    }
+                    NoteEvent::PolyModulation {
+                        voice_id,
+                        poly_modulation_id,
+                        normalized_offset,
+                        ..
+                    } => {
+                        for playing_sample in &mut self.playing_samples {
+                            if playing_sample.voice_id == Some(voice_id) {
+                                if let Some(offset) = playing_sample
+                                    .poly_offsets
+                                    .get_mut(poly_modulation_id as usize)
+                                {
+                                    *offset = normalized_offset;
+                                }
+                            }
+                        }
+                    }
+                    // the parameters' new values are picked up in the next block
+                    NoteEvent::MonoAutomation { .. } => {}
+                    NoteEvent::PolyVolume {
+                        voice_id,
+                        channel,
+                        note,
+                        gain,
+                        ..
+                    } => {
+                        self.set_expression(voice_id, channel, note, |e| e.gain = gain);
+                        self.forward(context, event);
+                    }
+                    NoteEvent::PolyPan {
+                        voice_id,
+                        channel,
+                        note,
+                        pan,
+                        ..
+                    } => {
+                        self.set_expression(voice_id, channel, note, |e| e.pan = pan);
+                        self.forward(context, event);
+                    }
+                    NoteEvent::PolyTuning {
+                        voice_id,
+                        channel,
+                        note,
+                        tuning,
+                        ..
+                    } => {
+                        self.set_expression(voice_id, channel, note, |e| e.tuning = tuning);
+                        self.forward(context, event);
+                    }
+                    NoteEvent::PolyBrightness {
+                        voice_id,
+                        channel,
+                        note,
+                        brightness,
+                        ..
+                    } => {
+                        self.set_expression(voice_id, channel, note, |e| e.brightness = brightness);
+                        self.forward(context, event);
+                    }
+                    NoteEvent::Choke {
+                        voice_id,
+                        channel,
+                        note,
+                        ..
+                    } => {
+                        for playing_sample in &mut self.playing_samples {
+                            if playing_sample.is_voice(voice_id, channel, note) {
+                                playing_sample.choke();
+                            }
+                        }
+                        self.forward(context, event);
+                    }
+                    NoteEvent::NoteOn {
+                        voice_id,
+                        channel,
+                        note,
+                        velocity,
+                        ..
+                    } if note == trigger_note
+                        && (velocity * 127.0) as u8 >= min_velocity as u8
+                        && (velocity * 127.0) as u8 <= max_velocity as u8 =>
                     {
-                        // None if no samples are loaded
-                        if let Some((path, _sample_data)) =
-                            // Get a random sample but based on the current sample position in
-                            // project
-                            self.loaded_samples
-                                    .iter()
-                                    .choose(&mut StdRng::seed_from_u64(
-                                        start_sample.unsigned_abs() + event.timing() as u64,
-                                    ))
-                        {
-                            let mut playing_sample = PlayingSample::new(
-                                path.clone(),
-                                self.velocity_to_gain((velocity * 127.0) as u8),
-                            );
-
-                            // start at correct position in buffer
-                            playing_sample.position = -(event.timing() as isize);
-
-                            self.playing_samples.push(playing_sample);
+                        if let Some(index) = self.trigger(
+                            context,
+                            note,
+                            velocity,
+                            event.timing(),
+                            start_sample,
+                            false,
+                        ) {
+                            // the voice that was just started
+                            if let Some(playing_sample) = self.playing_samples.last_mut() {
+                                playing_sample.voice_id = voice_id;
+                                playing_sample.channel = channel;
+                                playing_sample.from_note_on = true;
+                                if mpe.is_some_and(|mpe| mpe.is_member_channel(channel)) {
+                                    playing_sample.mpe = self.mpe_channels[channel as usize & 15];
+                                }
+                            }
+                            self.pad
+                                .sample_report
+                                .send(context, event.timing(), index, velocity);
+                        }
+                        if self.pad.routing == Routing::PassAll {
+                            self.forward(context, event);
+                        } else {
+                            self.consumed_notes[channel as usize & 15] |= 1 << note;
                         }
                     }
-                    event => context.send_event(event),
+                    NoteEvent::NoteOff {
+                        voice_id,
+                        channel,
+                        note,
+                        ..
+                    } => {
+                        // matched by the voices the note started rather than the trigger note,
+                        // which may have changed while the note was held. Auditions, previews and
+                        // pedal chicks aren't held by a note.
+                        for playing_sample in &mut self.playing_samples {
+                            if playing_sample.from_note_on
+                                && playing_sample.is_voice(voice_id, channel, note)
+                            {
+                                playing_sample.release_at = Some(event.timing() as usize);
+                            }
+                        }
+                        let consumed_notes = &mut self.consumed_notes[channel as usize & 15];
+                        if *consumed_notes & (1 << note) != 0 {
+                            *consumed_notes &= !(1 << note);
+                        } else {
+                            self.forward(context, event);
+                        }
+                    }
+                    event => self.forward(context, event),
                     // _ => {}
                 }
                 next_event = context.next_event();
@@ -327,47 +1191,246 @@ impl NihSampler {
         }
     }
 
-    /// Loads a sample at the given filepath, overwriting any sample loaded with the given path
-    fn load_sample(&mut self, path: PathBuf) {
-        // wav only for now
-        let reader = hound::WavReader::open(&path);
-        if let Ok(mut reader) = reader {
-            let spec = reader.spec();
-            let sample_rate = spec.sample_rate as f32;
-            let channels = spec.channels as usize;
-
-            let interleaved_samples = match spec.sample_format {
-                hound::SampleFormat::Int => reader
-                    .samples::<i32>()
-                    .map(|s| (s.unwrap_or_default() as f32 * 256.0) / i32::MAX as f32)
-                    .collect::<Vec<f32>>(),
-                hound::SampleFormat::Float => reader
-                    .samples::<f32>()
-                    .map(|s| s.unwrap_or_default())
-                    .collect::<Vec<f32>>(),
-            };
+    /// Applies a note expression to the voices it's meant for
+    fn set_expression(
+        &mut self,
+        voice_id: Option<i32>,
+        channel: u8,
+        note: u8,
+        set: impl Fn(&mut Expression),
+    ) {
+        for playing_sample in &mut self.playing_samples {
+            if playing_sample.is_voice(voice_id, channel, note) {
+                set(&mut playing_sample.expression);
+            }
+        }
+    }
 
-            let mut samples = uninterleave(interleaved_samples, channels);
+    /// Applies a pitch bend, pressure or timbre event on an MPE member channel to the channel's
+    /// voices
+    fn apply_mpe(&mut self, mpe: &Mpe, channel: u8, event: &PluginNoteEvent<Self>) {
+        let expression = &mut self.mpe_channels[channel as usize & 15];
+        mpe.apply(event, expression);
 
-            // resample if needed
-            if sample_rate != self.sample_rate {
-                samples = resample(samples, sample_rate, self.sample_rate);
+        for playing_sample in &mut self.playing_samples {
+            if playing_sample.channel == channel {
+                playing_sample.mpe = *expression;
             }
+        }
+    }
 
-            self.loaded_samples.insert(path.clone(), samples);
+    /// Passes an incoming event on to the output, following the routing policy
+    fn forward(&self, context: &mut impl ProcessContext<Self>, event: PluginNoteEvent<Self>) {
+        if self.pad.routing == Routing::ConsumeAll {
+            return;
         }
+        if let Some(event) = routing::transpose(event, self.pad.output_transpose) {
+            context.send_event(event);
+        }
+    }
 
-        if !self.params.sample_list.lock().unwrap().contains(&path) {
-            self.params.sample_list.lock().unwrap().push(path);
+    /// Picks a sample for a note with a velocity between 0 and 1 and starts playing it. On hi-hat
+    /// pads, samples are picked by the pedal position, or from the pedal chick samples when the
+    /// pedal closes. Returns the index of the sample in the sample list.
+    fn trigger(
+        &mut self,
+        context: &mut impl ProcessContext<Self>,
+        note: u8,
+        velocity: f32,
+        timing: u32,
+        start_sample: i64,
+        pedal_chick: bool,
+    ) -> Option<usize> {
+        let normalized_velocity = velocity;
+        let velocity = (velocity * 127.0).round() as u8;
+        // Get a random sample but based on the current sample position in project
+        let mut rng = StdRng::seed_from_u64(start_sample.unsigned_abs() + timing as u64);
+        let random = rng.gen::<f32>();
+        let note_count = self.note_count;
+        if !pedal_chick {
+            self.note_count = self.note_count.wrapping_add(1);
         }
+
+        // the pedal position, moved by the routes to the openness in the modulation matrix
+        let pad = self.pad.clone();
+        let hi_hat = pad.hi_hat;
+        let openness = hi_hat.filter(|_| !pedal_chick).map(|hi_hat| {
+            let modulation = self
+                .mod_state
+                .evaluate(&pad.mod_routes, normalized_velocity, random, 0.0);
+            (self.mod_state.cc[hi_hat.cc as usize & 127] * 127.0 + modulation.openness)
+                .round()
+                .clamp(0.0, 127.0) as u8
+        });
+
+        // nothing is played if no samples are loaded or none of them match. The samples are
+        // walked twice instead of being collected, so nothing is allocated.
+        let samples = &pad.samples;
+        let candidates = move || {
+            samples.iter().filter(move |sample| {
+                (hi_hat.is_none() || sample.zone.pedal_chick == pedal_chick)
+                    && sample.zone.matches(velocity, openness, note_count, random)
+            })
+        };
+        let total_weight = candidates()
+            .map(|sample| sample.zone.weight.max(0.0))
+            .sum::<f32>();
+        if total_weight <= 0.0 {
+            return None;
+        }
+        let mut remaining = rng.gen::<f32>() * total_weight;
+        let sample = candidates()
+            .find(|sample| {
+                remaining -= sample.zone.weight.max(0.0);
+                remaining < 0.0
+            })
+            // rounding can leave a sliver of weight at the end
+            .or_else(|| {
+                candidates()
+                    .filter(|sample| sample.zone.weight > 0.0)
+                    .last()
+            })?;
+
+        self.start_voice(context, sample, note, normalized_velocity, random, timing);
+        if let Some(playing_sample) = self.playing_samples.last_mut() {
+            playing_sample.open_hat = hi_hat
+                .zip(openness)
+                .is_some_and(|(hi_hat, openness)| openness >= hi_hat.close_threshold);
+            // pedal chicks are never released, so like auditions they skip their loop
+            if pedal_chick {
+                playing_sample.loop_range = None;
+            }
+        }
+
+        Some(sample.index)
     }
 
-    fn remove_sample(&mut self, path: PathBuf) {
-        let mut sample_list = self.params.sample_list.lock().unwrap();
-        if let Some(index) = sample_list.iter().position(|e| e == &path) {
-            sample_list.remove(index);
+    /// Plays a sample from the editor. Loops are skipped so the voice ends without a note off.
+    fn audition(&mut self, context: &mut impl ProcessContext<Self>, path: &Path, velocity: f32) {
+        let pad = self.pad.clone();
+        let Some(sample) = pad.sample(path) else {
+            return;
+        };
+        let note = self.param_value("note", &self.params.note) as u8;
+        self.start_voice(context, sample, note, velocity, random(), 0);
+        if let Some(playing_sample) = self.playing_samples.last_mut() {
+            playing_sample.loop_range = None;
+        }
+    }
+
+    fn preview(
+        &mut self,
+        context: &mut impl ProcessContext<Self>,
+        path: Arc<Path>,
+        sample: Arc<LoadedSample>,
+        velocity: f32,
+    ) {
+        if let Some(last_preview) = &self.last_preview {
+            for playing_sample in &mut self.playing_samples {
+                if &playing_sample.handle == last_preview {
+                    playing_sample.choke();
+                }
+            }
+        }
+
+        let mut playing_sample = PlayingSample::new(
+            path.clone(),
+            self.param_value("note", &self.params.note) as u8,
+            sample,
+            self.velocity_to_gain((velocity * 127.0) as u8),
+            self.sample_rate,
+        );
+        playing_sample.velocity = velocity;
+        self.push_voice(context, playing_sample);
+        if let Some(last_preview) = self.last_preview.replace(path) {
+            context.execute_background(Task::Drop(Garbage::Path(last_preview)));
+        }
+    }
+
+    /// Starts a voice for a loaded sample with the settings of its zone, choking the voices it's
+    /// off by
+    fn start_voice(
+        &mut self,
+        context: &mut impl ProcessContext<Self>,
+        sample: &PadSample,
+        note: u8,
+        velocity: f32,
+        random: f32,
+        timing: u32,
+    ) {
+        let zone = &sample.zone;
+        let modulation = self
+            .mod_state
+            .evaluate(&self.pad.mod_routes, velocity, random, 0.0);
+
+        if let Some(group) = zone.group {
+            for playing_sample in &mut self.playing_samples {
+                if playing_sample.off_by == Some(group) {
+                    playing_sample.choke();
+                }
+            }
+        }
+
+        let mut playing_sample = PlayingSample::new(
+            sample.path.clone(),
+            note,
+            sample.sample.clone(),
+            self.velocity_to_gain((velocity * 127.0) as u8) * util::db_to_gain(zone.volume),
+            self.sample_rate,
+        );
+        playing_sample.position =
+            ((zone.start + modulation.start_offset) * self.sample_rate) as f64;
+        if let Some(end) = zone.end {
+            playing_sample.end = playing_sample.end.min((end * self.sample_rate) as f64);
+        }
+        playing_sample.speed = 2.0f64.powf(zone.tune as f64 / 1200.0);
+        playing_sample.reverse = zone.reverse;
+        playing_sample.pan = (zone.pan / 100.0).clamp(-1.0, 1.0);
+        playing_sample.off_by = zone.off_by;
+        if let (Some(loop_start), Some(loop_end)) = (zone.loop_start, zone.loop_end) {
+            playing_sample.loop_range = Some((
+                (loop_start * self.sample_rate) as f64,
+                (loop_end * self.sample_rate) as f64,
+            ));
+        }
+        playing_sample.envelope = zone.envelope.clone();
+        playing_sample.velocity = velocity;
+        playing_sample.random = random;
+        playing_sample.set_modulation(modulation);
+
+        // start at correct position in buffer
+        playing_sample.delay = timing as usize;
+
+        self.push_voice(context, playing_sample);
+    }
+
+    /// Adds a voice. When all voices are in use the oldest one is stolen, preferring the ones that
+    /// are fading out already, so there are never more voices than the host was told about.
+    fn push_voice(
+        &mut self,
+        context: &mut impl ProcessContext<Self>,
+        playing_sample: PlayingSample,
+    ) {
+        if self.playing_samples.len() >= MAX_VOICES {
+            let index = self
+                .playing_samples
+                .iter()
+                .position(|e| e.is_choked())
+                .unwrap_or(0);
+            // removed in place and the new voice goes at the end, so the oldest voices stay in
+            // front
+            let stolen = self.playing_samples.remove(index);
+            if stolen.from_note_on {
+                context.send_event(NoteEvent::VoiceTerminated {
+                    timing: playing_sample.delay as u32,
+                    voice_id: stolen.voice_id,
+                    channel: stolen.channel,
+                    note: stolen.note,
+                });
+            }
+            context.execute_background(Task::Drop(Garbage::Voice(stolen)));
         }
-        self.loaded_samples.remove(&path);
+        self.playing_samples.push(playing_sample);
     }
 }
 
@@ -376,6 +1439,10 @@ impl ClapPlugin for NihSampler {
     const CLAP_DESCRIPTION: Option<&'static str> = Some("A simple random-selection sampler");
     const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
     const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_POLY_MODULATION_CONFIG: Option<PolyModulationConfig> = Some(PolyModulationConfig {
+        max_voice_capacity: MAX_VOICES as u32,
+        supports_overlapping_voices: true,
+    });
     const CLAP_FEATURES: &'static [ClapFeature] = &[
         ClapFeature::AudioEffect,
         ClapFeature::Stereo,
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{OnceLock, RwLock},
+};
+
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// User configuration shared by all instances, stored in `nih-sampler/config.toml` in the user's
+/// config directory. For example:
+///
+/// ```toml
+/// [library_roots]
+/// DRUMS = "/home/me/Samples/Drums"
+/// ```
+///
+/// Sample paths inside a library root are saved as `$DRUMS/Snare/Hit_01.wav`, so projects can be
+/// opened on machines where the library lives somewhere else. The favourite folders of the sample
+/// browser are stored here as well.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LibraryConfig {
+    #[serde(default)]
+    pub library_roots: BTreeMap<String, PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub favourite_folders: Vec<PathBuf>,
+}
+
+fn config() -> &'static RwLock<LibraryConfig> {
+    static CONFIG: OnceLock<RwLock<LibraryConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| RwLock::new(LibraryConfig::default()))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("nih-sampler").join("config.toml"))
+}
+
+/// Rereads the config file, falling back to no library roots if it's missing or invalid
+pub fn reload() {
+    let library_config = config_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|contents| {
+            toml::from_str(&contents).unwrap_or_else(|e| {
+                nih_warn!("invalid library config: {e}");
+                LibraryConfig::default()
+            })
+        })
+        .unwrap_or_default();
+
+    *config().write().unwrap() = library_config;
+}
+
+pub fn favourite_folders() -> Vec<PathBuf> {
+    config().read().unwrap().favourite_folders.clone()
+}
+
+/// Adds a folder to the browser's favourites, or removes it if it already is one, and saves the
+/// config file
+pub fn toggle_favourite_folder(folder: &Path) {
+    // every instance resolves its sample paths through the config, so the file is written after
+    // releasing the lock
+    let contents = {
+        let mut config = config().write().unwrap();
+        match config.favourite_folders.iter().position(|e| e == folder) {
+            Some(index) => {
+                config.favourite_folders.remove(index);
+            }
+            None => config.favourite_folders.push(folder.to_path_buf()),
+        }
+        toml::to_string_pretty(&*config)
+    };
+
+    let Some(path) = config_path() else {
+        return;
+    };
+    let result = contents.map_err(|e| e.to_string()).and_then(|contents| {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, contents).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        nih_warn!("failed to save library config to {path:?}: {e}");
+    }
+}
+
+/// Turns an absolute path into one relative to the library root containing it. When several roots
+/// contain the path, the most specific one is used. Paths outside of any root are kept as is.
+pub fn to_portable(path: &Path) -> PathBuf {
+    let config = config().read().unwrap();
+    let best_root = config
+        .library_roots
+        .iter()
+        .filter(|(_, root)| path.starts_with(root))
+        .max_by_key(|(_, root)| root.components().count());
+
+    match best_root {
+        Some((name, root)) => {
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>();
+            // always separated with forward slashes so the path also resolves on other platforms
+            PathBuf::from(format!("${name}/{}", relative.join("/")))
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// Turns a path created with [`to_portable`] back into an absolute path. Paths with a root that
+/// isn't configured on this machine are returned unchanged and will show up as missing.
+pub fn resolve(path: &Path) -> PathBuf {
+    let path_string = path.to_string_lossy();
+    let Some(rest) = path_string.strip_prefix('$') else {
+        return path.to_path_buf();
+    };
+
+    let (name, relative) = rest.split_once(['/', '\\']).unwrap_or((rest, ""));
+    match config().read().unwrap().library_roots.get(name) {
+        Some(root) => root.join(
+            relative
+                .split(['/', '\\'])
+                .filter(|component| !component.is_empty())
+                .collect::<PathBuf>(),
+        ),
+        None => path.to_path_buf(),
+    }
+}
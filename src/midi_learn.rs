@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use nih_plug::prelude::*;
+
+/// A parameter that can be controlled with a MIDI CC
+pub struct LearnableParam {
+    pub id: String,
+    pub name: String,
+    pub ptr: ParamPtr,
+}
+
+/// Every parameter in the params struct, in its order. Learn targets and captured CCs refer to
+/// them by their index in this list.
+pub fn learnable_params(params: &impl Params) -> Vec<LearnableParam> {
+    params
+        .param_map()
+        .into_iter()
+        .map(|(id, ptr, _)| LearnableParam {
+            // the parameters live as long as the params struct the pointers were taken from
+            name: unsafe { ptr.name() }.to_string(),
+            id,
+            ptr,
+        })
+        .collect()
+}
+
+/// What the next incoming MIDI event is captured for
+#[derive(Clone, Copy, PartialEq)]
+pub enum LearnTarget {
+    /// The next note becomes the trigger note
+    Note,
+    /// The next CC controls the learnable parameter with this index
+    Param(usize),
+}
+
+/// A MIDI event for the editor, sent from the audio thread
+#[derive(Clone, Copy)]
+pub enum Learned {
+    Note(u8),
+    Cc { param: usize, cc: u8 },
+    /// The latest value of a CC that moved. The editor sets the parameters mapped to it, so the
+    /// host sees the change.
+    CcMoved { cc: u8, value: f32 },
+}
+
+/// The latest values of the CCs mapped to parameters. The audio thread applies them to the
+/// parameters it reads, so the mappings also work while the editor is closed. The editor sets the
+/// parameters themselves when it's open, after which their own values are used again.
+pub struct CcOverrides([Option<f32>; 128]);
+
+impl Default for CcOverrides {
+    fn default() -> Self {
+        Self([None; 128])
+    }
+}
+
+impl CcOverrides {
+    /// Takes the value of a CC that moved, if a parameter is mapped to it
+    pub fn set(&mut self, cc_mappings: &BTreeMap<String, u8>, cc: u8, value: f32) {
+        if cc_mappings.values().any(|mapped| *mapped == cc) {
+            self.0[cc as usize & 127] = Some(value);
+        }
+    }
+
+    /// The normalized value of a parameter, with the value of the CC mapped to it in place of the
+    /// parameter's own value. The host's modulation is still added to it.
+    pub fn normalized<P: Param>(
+        &self,
+        cc_mappings: &BTreeMap<String, u8>,
+        id: &str,
+        param: &P,
+    ) -> f32 {
+        let overridden = cc_mappings
+            .get(id)
+            .and_then(|cc| self.0[*cc as usize & 127]);
+        match overridden {
+            Some(value) => (value + param.modulated_normalized_value()
+                - param.unmodulated_normalized_value())
+            .clamp(0.0, 1.0),
+            None => param.modulated_normalized_value(),
+        }
+    }
+
+    pub fn plain<P: Param>(
+        &self,
+        cc_mappings: &BTreeMap<String, u8>,
+        id: &str,
+        param: &P,
+    ) -> P::Plain {
+        param.preview_plain(self.normalized(cc_mappings, id, param))
+    }
+
+    /// Forgets the values of the CCs that aren't mapped anymore, and of the ones every parameter
+    /// mapped to them caught up with according to `caught_up`
+    pub fn release(
+        &mut self,
+        cc_mappings: &BTreeMap<String, u8>,
+        caught_up: impl Fn(&str, f32) -> bool,
+    ) {
+        for (cc, overridden) in self.0.iter_mut().enumerate() {
+            let Some(value) = *overridden else {
+                continue;
+            };
+            let mut mapped = cc_mappings
+                .iter()
+                .filter(|(_, mapped)| **mapped as usize == cc)
+                .peekable();
+            if mapped.peek().is_none() || mapped.all(|(id, _)| caught_up(id, value)) {
+                *overridden = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Cutoff of the voice filter when it's fully open, where it's bypassed
+pub const MAX_CUTOFF: f32 = 20_000.0;
+pub const MIN_CUTOFF: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ModSource {
+    Velocity,
+    /// A different random value for every note
+    Random,
+    PitchBend,
+    /// Channel pressure
+    Aftertouch,
+    /// Polyphonic aftertouch on the voice's note
+    PolyPressure,
+    Cc(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ModDestination {
+    /// In semitones
+    Pitch,
+    /// In dB
+    Gain,
+    /// In octaves relative to the cutoff parameter, positive amounts open the filter
+    FilterCutoff,
+    /// In seconds, only applied when a voice starts
+    StartOffset,
+    /// In pedal positions (0 to 127) added to the hi-hat pedal's CC when a sample is picked, only
+    /// applied when a voice starts
+    HiHatOpenness,
+}
+
+/// How a source's value is shaped before it's scaled by the amount
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    Linear,
+    Exponential,
+    Logarithmic,
+    SCurve,
+}
+
+/// A connection in the modulation matrix
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    /// The change at the source's maximum, in the destination's unit
+    pub amount: f32,
+    pub curve: Curve,
+}
+
+impl ModSource {
+    pub const ALL: [ModSource; 6] = [
+        ModSource::Velocity,
+        ModSource::Random,
+        ModSource::PitchBend,
+        ModSource::Aftertouch,
+        ModSource::PolyPressure,
+        ModSource::Cc(1),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            ModSource::Velocity => "Velocity".into(),
+            ModSource::Random => "Random".into(),
+            ModSource::PitchBend => "Pitch bend".into(),
+            ModSource::Aftertouch => "Aftertouch".into(),
+            ModSource::PolyPressure => "Poly pressure".into(),
+            ModSource::Cc(cc) => format!("CC {cc}"),
+        }
+    }
+
+    /// Whether the value only changes when a note starts
+    pub fn is_per_note(&self) -> bool {
+        matches!(self, ModSource::Velocity | ModSource::Random)
+    }
+}
+
+impl ModDestination {
+    pub const ALL: [ModDestination; 5] = [
+        ModDestination::Pitch,
+        ModDestination::Gain,
+        ModDestination::FilterCutoff,
+        ModDestination::StartOffset,
+        ModDestination::HiHatOpenness,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModDestination::Pitch => "Pitch",
+            ModDestination::Gain => "Gain",
+            ModDestination::FilterCutoff => "Cutoff",
+            ModDestination::StartOffset => "Start",
+            ModDestination::HiHatOpenness => "Openness",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            ModDestination::Pitch => " st",
+            ModDestination::Gain => " dB",
+            ModDestination::FilterCutoff => " oct",
+            ModDestination::StartOffset => " s",
+            ModDestination::HiHatOpenness => "",
+        }
+    }
+
+    /// A sensible increment for the amount when editing it
+    pub fn step(&self) -> f32 {
+        match self {
+            ModDestination::Pitch => 1.0,
+            ModDestination::Gain => 1.0,
+            ModDestination::FilterCutoff => 0.5,
+            ModDestination::StartOffset => 0.005,
+            ModDestination::HiHatOpenness => 8.0,
+        }
+    }
+}
+
+impl Curve {
+    pub const ALL: [Curve; 4] = [
+        Curve::Linear,
+        Curve::Exponential,
+        Curve::Logarithmic,
+        Curve::SCurve,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Curve::Linear => "Linear",
+            Curve::Exponential => "Exp",
+            Curve::Logarithmic => "Log",
+            Curve::SCurve => "S-curve",
+        }
+    }
+
+    /// Shapes a value between 0 and 1. Negative values (from the pitch bend) are mirrored.
+    pub fn apply(&self, value: f32) -> f32 {
+        let x = value.abs().min(1.0);
+        let shaped = match self {
+            Curve::Linear => x,
+            Curve::Exponential => x * x,
+            Curve::Logarithmic => 1.0 - (1.0 - x) * (1.0 - x),
+            Curve::SCurve => x * x * (3.0 - 2.0 * x),
+        };
+
+        shaped.copysign(value)
+    }
+}
+
+impl Default for ModRoute {
+    fn default() -> Self {
+        Self {
+            source: ModSource::Velocity,
+            destination: ModDestination::Gain,
+            amount: 0.0,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+/// The latest values of the channel-wide sources, kept on the audio thread
+pub struct ModState {
+    pub cc: [f32; 128],
+    /// Between -1 and 1
+    pub pitch_bend: f32,
+    pub aftertouch: f32,
+}
+
+impl Default for ModState {
+    fn default() -> Self {
+        Self {
+            cc: [0.0; 128],
+            pitch_bend: 0.0,
+            aftertouch: 0.0,
+        }
+    }
+}
+
+/// The combined effect of the modulation matrix on a voice
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Modulation {
+    /// Multiplier for the playback rate
+    pub speed: f64,
+    /// Linear gain
+    pub gain: f32,
+    /// Change of the lowpass cutoff in octaves, relative to the cutoff parameter
+    pub cutoff: f32,
+    /// In seconds
+    pub start_offset: f32,
+    /// Added to the hi-hat pedal position, in pedal positions
+    pub openness: f32,
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            gain: 1.0,
+            cutoff: 0.0,
+            start_offset: 0.0,
+            openness: 0.0,
+        }
+    }
+}
+
+impl ModState {
+    /// Evaluates the routes for a note with a velocity between 0 and 1, a random value and the
+    /// note's poly pressure
+    pub fn evaluate(
+        &self,
+        routes: &[ModRoute],
+        velocity: f32,
+        random: f32,
+        pressure: f32,
+    ) -> Modulation {
+        let (mut pitch, mut gain, mut cutoff, mut start_offset, mut openness) =
+            (0.0, 0.0, 0.0, 0.0, 0.0);
+        for route in routes {
+            let value = match route.source {
+                ModSource::Velocity => velocity,
+                ModSource::Random => random,
+                ModSource::PitchBend => self.pitch_bend,
+                ModSource::Aftertouch => self.aftertouch,
+                ModSource::PolyPressure => pressure,
+                ModSource::Cc(cc) => self.cc[cc as usize & 127],
+            };
+            let value = route.curve.apply(value) * route.amount;
+
+            match route.destination {
+                ModDestination::Pitch => pitch += value,
+                ModDestination::Gain => gain += value,
+                ModDestination::FilterCutoff => cutoff += value,
+                ModDestination::StartOffset => start_offset += value,
+                ModDestination::HiHatOpenness => openness += value,
+            }
+        }
+
+        Modulation {
+            speed: 2.0f64.powf(pitch as f64 / 12.0),
+            gain: util::db_to_gain(gain),
+            cutoff,
+            start_offset: start_offset.max(0.0),
+            openness,
+        }
+    }
+}
+
+/// The cutoff of a voice's filter, given the cutoff parameter and the modulation in octaves
+pub fn modulated_cutoff(cutoff: f32, octaves: f32) -> f32 {
+    (cutoff * 2.0f32.powf(octaves)).clamp(MIN_CUTOFF, MAX_CUTOFF)
+}
+
+/// Coefficient of a one-pole lowpass, `None` when the filter is fully open
+pub fn lowpass_coefficient(cutoff: f32, sample_rate: f32) -> Option<f32> {
+    (cutoff < MAX_CUTOFF).then(|| 1.0 - (-2.0 * PI * cutoff / sample_rate).exp())
+}
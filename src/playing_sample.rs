@@ -1,17 +1,235 @@
-use std::path::PathBuf;
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    kit::Envelope,
+    modulation::{self, Modulation},
+    poly::{Expression, VoiceControls, POLY_MOD_PARAMS},
+    LoadedSample,
+};
+
+/// Maximum number of channels the voice filter keeps state for
+const MAX_CHANNELS: usize = 8;
+
+/// How long it takes for a choked voice to fade out, in seconds
+const CHOKE_TIME: f32 = 0.005;
 
 pub struct PlayingSample {
-    pub handle: PathBuf,
-    pub position: isize,
+    pub handle: Arc<Path>,
+    /// The note that triggered the voice
+    pub note: u8,
+    pub channel: u8,
+    /// The host's id for the note, used for poly modulation and note expressions
+    pub voice_id: Option<i32>,
+    /// Started by a note on, and released by the matching note off
+    pub from_note_on: bool,
+    pub sample: Arc<LoadedSample>,
+    pub sample_rate: f32,
+    /// Number of samples to wait before starting, used to start at the correct position in the
+    /// buffer
+    pub delay: usize,
+    /// Position in the sample data, fractional when the sample is pitched
+    pub position: f64,
+    /// Position at which the voice stops
+    pub end: f64,
+    /// Playback rate, 1.0 plays the sample at its original pitch
+    pub speed: f64,
+    /// Reads the sample data backwards, with positions counting from its end
+    pub reverse: bool,
     pub gain: f32,
+    /// -1.0 is hard left, 1.0 is hard right
+    pub pan: f32,
+    /// The voice is choked when a sample in this group is played
+    pub off_by: Option<u32>,
+    /// Start and end of the loop in the sample data, looped until the voice is released
+    pub loop_range: Option<(f64, f64)>,
+    pub envelope: Option<Envelope>,
+    /// Index in the current buffer at which the voice gets released
+    pub release_at: Option<usize>,
+    /// Velocity between 0 and 1 and the random value of the note, kept to evaluate the
+    /// modulation again when a controller moves
+    pub velocity: f32,
+    pub random: f32,
+    /// Latest poly pressure on the voice's note
+    pub pressure: f32,
+    /// Started while the hi-hat pedal was open, choked when the pedal closes
+    pub open_hat: bool,
+    /// Normalized offsets the host added to the poly modulated parameters, by poly modulation id
+    pub poly_offsets: [f32; POLY_MOD_PARAMS],
+    pub expression: Expression,
+    /// Expressions of the voice's MPE member channel, kept apart from the host's note expressions
+    pub mpe: Expression,
+    modulation: Modulation,
+    controls: VoiceControls,
+    /// Lowpass coefficient for the cutoff parameter moved by the modulation matrix, `None` when the
+    /// filter is bypassed
+    filter_coefficient: Option<f32>,
+    filter_state: [f32; MAX_CHANNELS],
+    /// Seconds since the voice started
+    age: f32,
+    /// The age and envelope level at the time the voice was released
+    released: Option<(f32, f32)>,
+    /// Gain decrease per sample while the voice is being choked
+    fade_out: Option<f32>,
 }
 
 impl PlayingSample {
-    pub fn new(handle: PathBuf, gain: f32) -> Self {
+    pub fn new(
+        handle: Arc<Path>,
+        note: u8,
+        sample: Arc<LoadedSample>,
+        gain: f32,
+        sample_rate: f32,
+    ) -> Self {
         Self {
             handle,
-            position: 0,
+            note,
+            channel: 0,
+            voice_id: None,
+            from_note_on: false,
+            end: sample.len() as f64,
+            sample,
+            sample_rate,
+            delay: 0,
+            position: 0.0,
+            speed: 1.0,
+            reverse: false,
             gain,
+            pan: 0.0,
+            off_by: None,
+            loop_range: None,
+            envelope: None,
+            release_at: None,
+            velocity: 1.0,
+            random: 0.0,
+            pressure: 0.0,
+            open_hat: false,
+            poly_offsets: [0.0; POLY_MOD_PARAMS],
+            expression: Expression::default(),
+            mpe: Expression::default(),
+            modulation: Modulation::default(),
+            controls: VoiceControls::default(),
+            filter_coefficient: None,
+            filter_state: [0.0; MAX_CHANNELS],
+            age: 0.0,
+            released: None,
+            fade_out: None,
+        }
+    }
+
+    /// Quickly fades out the voice
+    pub fn choke(&mut self) {
+        if self.fade_out.is_none() {
+            self.fade_out = Some(self.gain / (CHOKE_TIME * self.sample_rate));
+        }
+    }
+
+    /// Whether an event for a voice id, or for a channel and note when the host didn't give an id,
+    /// is meant for this voice
+    pub fn is_voice(&self, voice_id: Option<i32>, channel: u8, note: u8) -> bool {
+        match voice_id {
+            Some(voice_id) => self.voice_id == Some(voice_id),
+            None => self.channel == channel && self.note == note,
+        }
+    }
+
+    pub fn is_choked(&self) -> bool {
+        self.fade_out.is_some()
+    }
+
+    pub fn set_modulation(&mut self, modulation: Modulation) {
+        self.modulation = modulation;
+        self.update_filter();
+    }
+
+    pub fn set_controls(&mut self, controls: VoiceControls) {
+        if self.controls != controls {
+            self.controls = controls;
+            self.update_filter();
+        }
+    }
+
+    fn update_filter(&mut self) {
+        self.filter_coefficient = modulation::lowpass_coefficient(
+            modulation::modulated_cutoff(self.controls.cutoff, self.modulation.cutoff),
+            self.sample_rate,
+        );
+    }
+
+    /// Stops looping and starts the release of the envelope
+    pub fn release(&mut self) {
+        self.release_at = None;
+        if self.released.is_none() {
+            self.released = Some((self.age, self.envelope_level()));
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        let release_done = match (&self.envelope, self.released) {
+            (Some(envelope), Some((released_at, _))) => self.age - released_at >= envelope.release,
+            _ => false,
+        };
+
+        release_done || self.position >= self.end || self.position < 0.0 || self.gain <= 0.0
+    }
+
+    /// Gain for one of the output channels, taking the panning and envelope into account
+    pub fn channel_gain(&self, channel_index: usize, channels: usize) -> f32 {
+        let pan = (self.pan + self.controls.pan).clamp(-1.0, 1.0);
+        let pan_gain = match (channels, channel_index) {
+            (2, 0) => (1.0 - pan).min(1.0),
+            (2, 1) => (1.0 + pan).min(1.0),
+            _ => 1.0,
+        };
+
+        self.gain * self.modulation.gain * self.controls.gain * self.envelope_level() * pan_gain
+    }
+
+    /// Value of one of the channels at the current position
+    pub fn value(&self, channel_index: usize) -> f32 {
+        let position = if self.reverse {
+            self.sample.len() as f64 - 1.0 - self.position
+        } else {
+            self.position
+        };
+        self.sample.sample_at(channel_index, position)
+    }
+
+    /// Runs a sample of one of the channels through the voice filter
+    pub fn filter(&mut self, channel_index: usize, value: f32) -> f32 {
+        match (
+            self.filter_coefficient,
+            self.filter_state.get_mut(channel_index),
+        ) {
+            (Some(coefficient), Some(state)) => {
+                *state += coefficient * (value - *state);
+                *state
+            }
+            _ => value,
+        }
+    }
+
+    fn envelope_level(&self) -> f32 {
+        match (&self.envelope, self.released) {
+            (None, _) => 1.0,
+            (Some(envelope), None) => envelope.level(self.age),
+            (Some(envelope), Some((released_at, level))) => envelope
+                .release_level(self.age - released_at, level)
+                .unwrap_or(0.0),
+        }
+    }
+
+    /// Moves on to the next sample
+    pub fn advance(&mut self) {
+        self.position += self.speed * self.modulation.speed * self.controls.speed;
+        if let Some((loop_start, loop_end)) = self.loop_range {
+            if self.released.is_none() && self.position >= loop_end && loop_end > loop_start {
+                self.position -= loop_end - loop_start;
+            }
+        }
+
+        self.age += 1.0 / self.sample_rate;
+        if let Some(fade_out) = self.fade_out {
+            self.gain -= fade_out;
         }
     }
 }
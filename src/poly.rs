@@ -0,0 +1,144 @@
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::modulation::MAX_CUTOFF;
+
+/// Poly modulation ids of the parameters the host can modulate per voice, also used as indices
+/// into a voice's offsets
+pub const GAIN_POLY_MOD_ID: u32 = 0;
+pub const PAN_POLY_MOD_ID: u32 = 1;
+pub const PITCH_POLY_MOD_ID: u32 = 2;
+pub const CUTOFF_POLY_MOD_ID: u32 = 3;
+pub const POLY_MOD_PARAMS: usize = 4;
+
+/// Maximum number of voices, the oldest voice is choked when a note would go over it
+pub const MAX_VOICES: usize = 64;
+
+/// How far the filter closes at zero brightness, in octaves
+const BRIGHTNESS_RANGE: f32 = 8.0;
+
+/// In MPE mode the first channel is the master channel, whose events apply to all voices
+const MPE_MASTER_CHANNEL: u8 = 0;
+
+/// The CC MPE controllers send the timbre on
+pub const MPE_TIMBRE_CC: u8 = 74;
+
+/// How much full pressure raises a voice's gain in MPE mode, in dB
+const MPE_PRESSURE_RANGE: f32 = 6.0;
+
+/// Note expressions the host or an MPE controller sent for a single voice
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Expression {
+    /// Linear gain
+    pub gain: f32,
+    /// -1 is hard left, 1 is hard right
+    pub pan: f32,
+    /// In semitones
+    pub tuning: f32,
+    /// Between 0 and 1, where 1 leaves the filter open
+    pub brightness: f32,
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pan: 0.0,
+            tuning: 0.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+impl Expression {
+    /// Stacks another set of expressions for the same voice on top of these
+    pub fn combine(&self, other: &Expression) -> Expression {
+        Expression {
+            gain: self.gain * other.gain,
+            pan: self.pan + other.pan,
+            tuning: self.tuning + other.tuning,
+            brightness: self.brightness * other.brightness,
+        }
+    }
+}
+
+/// The per-voice values of the poly modulated parameters, with the note expressions applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoiceControls {
+    /// Linear gain
+    pub gain: f32,
+    pub pan: f32,
+    /// Multiplier for the playback rate
+    pub speed: f64,
+    /// Lowpass cutoff in Hz
+    pub cutoff: f32,
+}
+
+impl Default for VoiceControls {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pan: 0.0,
+            speed: 1.0,
+            cutoff: MAX_CUTOFF,
+        }
+    }
+}
+
+impl VoiceControls {
+    /// Combines the parameter values for a voice, given as gain, pan, pitch in semitones and cutoff,
+    /// with its note expressions
+    pub fn new(gain: f32, pan: f32, pitch: f32, cutoff: f32, expression: &Expression) -> Self {
+        Self {
+            gain: gain * expression.gain,
+            pan: pan + expression.pan,
+            speed: 2.0f64.powf((pitch + expression.tuning) as f64 / 12.0),
+            cutoff: cutoff
+                * 2.0f32.powf((expression.brightness.clamp(0.0, 1.0) - 1.0) * BRIGHTNESS_RANGE),
+        }
+    }
+}
+
+/// Settings of MPE mode, where every note gets its own channel and the pitch bend, pressure and
+/// timbre on that channel control the note's voices
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mpe {
+    /// In semitones
+    pub pitch_bend_range: f32,
+}
+
+impl Default for Mpe {
+    fn default() -> Self {
+        Self {
+            pitch_bend_range: 48.0,
+        }
+    }
+}
+
+impl Mpe {
+    pub fn is_member_channel(&self, channel: u8) -> bool {
+        channel != MPE_MASTER_CHANNEL
+    }
+
+    /// Applies a pitch bend, pressure or timbre event to the expression of the event's channel.
+    /// Pressure raises the gain, and a timbre below the center closes the filter.
+    pub fn apply<S>(&self, event: &NoteEvent<S>, expression: &mut Expression) {
+        match *event {
+            NoteEvent::MidiPitchBend { value, .. } => {
+                expression.tuning = (value - 0.5) * 2.0 * self.pitch_bend_range;
+            }
+            NoteEvent::MidiChannelPressure { pressure, .. } => {
+                expression.gain = util::db_to_gain(pressure * MPE_PRESSURE_RANGE);
+            }
+            NoteEvent::MidiCC {
+                cc: MPE_TIMBRE_CC,
+                value,
+                ..
+            } => {
+                expression.brightness = (value * 2.0).min(1.0);
+            }
+            _ => {}
+        }
+    }
+}
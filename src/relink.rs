@@ -0,0 +1,43 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::sample_pool;
+
+/// Searches `root` for the missing samples, given as their old path and the content hash of the
+/// file when it was last loaded. A file with the same name and contents is preferred, then a
+/// file with the same contents (it was renamed), and finally a file with the same name (it was
+/// edited). Returns pairs of old and new paths.
+pub fn find_relinks(root: &Path, missing: &[(PathBuf, Option<u64>)]) -> Vec<(PathBuf, PathBuf)> {
+    let candidates = sample_pool::supported_files_in(root);
+    let mut hashes = HashMap::new();
+
+    missing
+        .iter()
+        .filter_map(|(old_path, content_hash)| {
+            let same_name = |candidate: &&PathBuf| candidate.file_name() == old_path.file_name();
+
+            let found = match content_hash {
+                Some(content_hash) => candidates
+                    .iter()
+                    .filter(same_name)
+                    .chain(candidates.iter())
+                    .find(|candidate| hash_of(&mut hashes, candidate) == Some(*content_hash))
+                    .or_else(|| candidates.iter().find(same_name)),
+                None => candidates.iter().find(same_name),
+            }?;
+
+            Some((old_path.clone(), found.clone()))
+        })
+        .collect()
+}
+
+/// Hashes the file at the given path, remembering the result for subsequent lookups
+fn hash_of(hashes: &mut HashMap<PathBuf, Option<u64>>, path: &Path) -> Option<u64> {
+    *hashes.entry(path.to_path_buf()).or_insert_with(|| {
+        std::fs::read(path)
+            .ok()
+            .map(|bytes| sample_pool::content_hash(&bytes))
+    })
+}
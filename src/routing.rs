@@ -0,0 +1,53 @@
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Which incoming MIDI events are passed on to the plugin's output, so instances can be chained
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Routing {
+    /// Everything is passed on, including the notes that trigger this instance
+    PassAll,
+    /// Notes that trigger this instance are consumed, everything else is passed on
+    #[default]
+    ConsumeMatched,
+    /// Nothing is passed on
+    ConsumeAll,
+}
+
+impl Routing {
+    pub const ALL: [Routing; 3] = [
+        Routing::PassAll,
+        Routing::ConsumeMatched,
+        Routing::ConsumeAll,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Routing::PassAll => "Pass all",
+            Routing::ConsumeMatched => "Consume matched",
+            Routing::ConsumeAll => "Consume all",
+        }
+    }
+}
+
+/// Moves the note of a note event by a number of semitones. Events that end up outside of the MIDI
+/// note range are dropped.
+pub fn transpose<S>(mut event: NoteEvent<S>, semitones: i8) -> Option<NoteEvent<S>> {
+    let note = match &mut event {
+        NoteEvent::NoteOn { note, .. }
+        | NoteEvent::NoteOff { note, .. }
+        | NoteEvent::Choke { note, .. }
+        | NoteEvent::PolyPressure { note, .. }
+        | NoteEvent::PolyVolume { note, .. }
+        | NoteEvent::PolyPan { note, .. }
+        | NoteEvent::PolyTuning { note, .. }
+        | NoteEvent::PolyVibrato { note, .. }
+        | NoteEvent::PolyExpression { note, .. }
+        | NoteEvent::PolyBrightness { note, .. } => note,
+        _ => return Some(event),
+    };
+
+    *note = u8::try_from(*note as i32 + semitones as i32)
+        .ok()
+        .filter(|note| *note <= 127)?;
+    Some(event)
+}
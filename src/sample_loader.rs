@@ -0,0 +1,486 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+};
+
+use nih_plug::prelude::*;
+
+use crate::{
+    kit::{HiHat, KitSample, Pad, Zone},
+    kit_bank::PreloadedPad,
+    library,
+    modulation::ModRoute,
+    poly::Mpe,
+    routing::Routing,
+    sample_pool::{self, SampleStatus},
+    sample_report::SampleReport,
+    watcher::{ReloadedSample, SampleWatcher},
+    LoadedSample, NihSamplerParams, ThreadMessage,
+};
+
+/// The decoded samples by their path in the sample list
+pub type SampleMap = HashMap<PathBuf, Arc<LoadedSample>>;
+
+/// Everything the audio thread needs to play the pad, so notes are handled without locking the
+/// parameters or allocating. A new snapshot is published whenever the samples or settings change.
+#[derive(Default)]
+pub struct PadState {
+    /// The loaded samples in sample list order
+    pub samples: Vec<PadSample>,
+    pub hi_hat: Option<HiHat>,
+    pub choke_pressure: Option<f32>,
+    pub mod_routes: Vec<ModRoute>,
+    pub sample_report: SampleReport,
+    pub routing: Routing,
+    pub output_transpose: i8,
+    pub mpe: Option<Mpe>,
+    /// MIDI CCs controlling parameters, by parameter id
+    pub cc_mappings: BTreeMap<String, u8>,
+}
+
+pub struct PadSample {
+    pub path: Arc<Path>,
+    /// Position in the sample list, reported when the sample is picked
+    pub index: usize,
+    pub sample: Arc<LoadedSample>,
+    pub zone: Zone,
+}
+
+impl PadState {
+    /// Takes the samples in the order of the pad's sample list, skipping the ones that aren't
+    /// loaded
+    pub fn new(pad: &Pad, samples: &SampleMap) -> Self {
+        let samples = pad
+            .samples
+            .iter()
+            .enumerate()
+            .filter_map(|(index, kit_sample)| {
+                Some(PadSample {
+                    path: Arc::from(kit_sample.path.as_path()),
+                    index,
+                    sample: samples.get(&kit_sample.path)?.clone(),
+                    zone: kit_sample.zone.clone(),
+                })
+            })
+            .collect();
+
+        Self {
+            samples,
+            hi_hat: pad.hi_hat,
+            choke_pressure: pad.choke_pressure,
+            mod_routes: pad.mod_routes.clone(),
+            sample_report: pad.sample_report,
+            routing: pad.routing,
+            output_transpose: pad.output_transpose,
+            mpe: pad.mpe,
+            cc_mappings: pad.cc_mappings.clone(),
+        }
+    }
+
+    pub fn sample(&self, path: &Path) -> Option<&PadSample> {
+        self.samples.iter().find(|sample| &*sample.path == path)
+    }
+}
+
+/// A new state of the pad for the audio thread
+pub struct SampleUpdate {
+    /// Counts the switches to pads from the kit bank. The audio thread skips updates that were
+    /// made before its latest switch.
+    pub generation: u64,
+    pub pad: Arc<PadState>,
+}
+
+/// Loads and removes samples on the background thread, keeping the sample list and everything
+/// keyed by it up to date. After every change a new [`PadState`] is handed to the audio thread,
+/// which swaps it in without locking, allocating or freeing anything.
+pub struct SampleLoader {
+    params: Arc<NihSamplerParams>,
+    sample_status: Arc<Mutex<HashMap<PathBuf, SampleStatus>>>,
+    sample_rate: f32,
+    samples: Arc<SampleMap>,
+    generation: u64,
+    /// The latest update the audio thread hasn't picked up yet
+    update: Arc<Mutex<Option<SampleUpdate>>>,
+    /// The latest samples, for the editor's waveform view
+    current: Arc<Mutex<Arc<SampleMap>>>,
+    /// Only exists while the watch files setting is enabled
+    watcher: Option<SampleWatcher>,
+    reload_consumer: Option<rtrb::Consumer<ReloadedSample>>,
+    /// Set by the watcher when it reloaded samples, so the audio thread schedules
+    /// [`SampleLoader::apply_reloads()`]
+    reloads_pending: Arc<AtomicBool>,
+}
+
+impl SampleLoader {
+    pub fn new(
+        params: Arc<NihSamplerParams>,
+        sample_status: Arc<Mutex<HashMap<PathBuf, SampleStatus>>>,
+    ) -> Self {
+        Self {
+            params,
+            sample_status,
+            sample_rate: 44100.0,
+            samples: Arc::new(HashMap::new()),
+            generation: 0,
+            update: Arc::new(Mutex::new(None)),
+            current: Arc::new(Mutex::new(Arc::new(HashMap::new()))),
+            watcher: None,
+            reload_consumer: None,
+            reloads_pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Where the audio thread picks up the new pad state
+    pub fn update(&self) -> Arc<Mutex<Option<SampleUpdate>>> {
+        self.update.clone()
+    }
+
+    pub fn current(&self) -> Arc<Mutex<Arc<SampleMap>>> {
+        self.current.clone()
+    }
+
+    pub fn reloads_pending(&self) -> Arc<AtomicBool> {
+        self.reloads_pending.clone()
+    }
+
+    /// Loads the sample list and remembered folders again, at a new sample rate
+    pub fn reload_all(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.samples = Arc::new(HashMap::new());
+        // the watcher decodes at the current sample rate, so it's recreated when that changes
+        self.watcher = None;
+        self.reload_consumer = None;
+
+        // paths are stored relative to the library roots, which may have changed since the state
+        // was saved
+        library::reload();
+        let sample_list = self.params.sample_paths();
+        for path in sample_list {
+            let portable_path = library::to_portable(&library::resolve(&path));
+            if portable_path != path {
+                self.rename_sample(&path, &portable_path);
+            }
+            self.load_sample(portable_path);
+        }
+
+        let sample_folders = self.params.sample_folders.lock().unwrap().clone();
+        for path in sample_folders {
+            self.load_folder(path, false);
+        }
+
+        self.update_watcher();
+        self.publish();
+    }
+
+    /// Creates the file watcher when the watch files setting is enabled, watching every sample in
+    /// the list, and removes it when it's disabled
+    pub fn update_watcher(&mut self) {
+        let enabled = self.params.watch_files.value();
+        if enabled == self.watcher.is_some() {
+            return;
+        }
+        if !enabled {
+            self.watcher = None;
+            self.reload_consumer = None;
+            return;
+        }
+
+        (self.watcher, self.reload_consumer) =
+            match SampleWatcher::new(self.sample_rate, self.reloads_pending.clone()) {
+                Some((watcher, consumer)) => (Some(watcher), Some(consumer)),
+                None => (None, None),
+            };
+        if let Some(watcher) = &mut self.watcher {
+            for sample in self.params.samples.lock().unwrap().iter() {
+                watcher.watch(&library::resolve(&sample.path), &sample.path);
+            }
+        }
+    }
+
+    /// Makes a change to the samples requested by the editor
+    pub fn handle(&mut self, message: ThreadMessage) {
+        match message {
+            ThreadMessage::LoadSamples(paths) => {
+                for path in paths {
+                    self.load_sample(path);
+                }
+            }
+            ThreadMessage::RemoveSample(path) => self.remove_sample(&path),
+            ThreadMessage::RelinkSamples(relinks) => {
+                for (old_path, new_path) in relinks {
+                    self.relink_sample(&old_path, &new_path);
+                }
+            }
+            ThreadMessage::LoadFolder { path, remember } => self.load_folder(path, remember),
+            ThreadMessage::ForgetFolder(path) => {
+                self.params
+                    .sample_folders
+                    .lock()
+                    .unwrap()
+                    .retain(|e| e != &path);
+            }
+            ThreadMessage::LoadPad(pad) => self.load_pad(pad),
+            // the editor changed the settings, which only need to be published
+            ThreadMessage::UpdateSettings => {}
+            // the other messages are handled by the audio thread
+            _ => return,
+        }
+
+        self.publish();
+    }
+
+    /// Swaps in the samples the watcher decoded again after their files changed
+    pub fn apply_reloads(&mut self) {
+        let Some(reload_consumer) = &mut self.reload_consumer else {
+            return;
+        };
+
+        let mut reloaded_any = false;
+        while let Ok(reloaded) = reload_consumer.pop() {
+            if !self.params.has_sample(&reloaded.path) {
+                continue;
+            }
+
+            self.params
+                .sample_hashes
+                .lock()
+                .unwrap()
+                .insert(reloaded.path.clone(), reloaded.content_hash);
+            self.sample_status
+                .lock()
+                .unwrap()
+                .insert(reloaded.path.clone(), SampleStatus::Loaded);
+            Arc::make_mut(&mut self.samples).insert(reloaded.path, reloaded.sample);
+            reloaded_any = true;
+        }
+
+        if reloaded_any {
+            self.publish();
+        }
+    }
+
+    /// Catches up with the audio thread after it switched to a preloaded pad from the kit bank,
+    /// replacing the sample list and folders with the pad's. The parameters are left as they are.
+    pub fn switch_pad(&mut self, preloaded: &PreloadedPad, generation: u64) {
+        // the audio thread is already playing these, so updates from before the switch are stale
+        self.generation = generation;
+        self.samples = preloaded.sample_map.clone();
+
+        let samples = std::mem::take(&mut *self.params.samples.lock().unwrap());
+        if let Some(watcher) = &mut self.watcher {
+            for sample in &samples {
+                watcher.unwatch(&sample.path);
+            }
+        }
+        self.params.sample_hashes.lock().unwrap().clear();
+        self.params.embedded_samples.lock().unwrap().clear();
+        self.sample_status.lock().unwrap().clear();
+
+        for preloaded_sample in &preloaded.samples {
+            let path = &preloaded_sample.path;
+            if let Some(watcher) = &mut self.watcher {
+                watcher.watch(&library::resolve(path), path);
+            }
+            self.params.samples.lock().unwrap().push(KitSample {
+                path: path.clone(),
+                zone: preloaded_sample.zone.clone(),
+            });
+            self.params
+                .sample_hashes
+                .lock()
+                .unwrap()
+                .insert(path.clone(), preloaded_sample.content_hash);
+            self.sample_status
+                .lock()
+                .unwrap()
+                .insert(path.clone(), SampleStatus::Loaded);
+        }
+
+        *self.params.sample_folders.lock().unwrap() = preloaded.pad.folders.clone();
+        *self.params.pad_name.lock().unwrap() = preloaded.pad.name.clone();
+        *self.params.hi_hat.lock().unwrap() = preloaded.pad.hi_hat;
+        *self.params.choke_pressure.lock().unwrap() = preloaded.pad.choke_pressure;
+
+        self.publish();
+    }
+
+    /// Hands the current samples and settings to the audio thread, replacing an update it hasn't
+    /// picked up yet
+    fn publish(&mut self) {
+        let pad = PadState::new(&Pad::from_params(&self.params), &self.samples);
+        let update = SampleUpdate {
+            generation: self.generation,
+            pad: Arc::new(pad),
+        };
+        // an update that was never picked up is freed here, outside of the audio thread
+        let previous = self.update.lock().unwrap().replace(update);
+        drop(previous);
+        *self.current.lock().unwrap() = self.samples.clone();
+    }
+
+    /// Loads a sample at the given filepath, overwriting any sample loaded with the given path.
+    /// The decoded data is shared with other instances through the sample pool. If the file is
+    /// missing, the copy embedded in the plugin state is used instead.
+    fn load_sample(&mut self, path: PathBuf) {
+        // the sample list only contains paths relative to the library roots when possible
+        let path = library::to_portable(&library::resolve(&path));
+        let file_path = library::resolve(&path);
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(&file_path, &path);
+        }
+
+        let status = match sample_pool::load(&file_path, self.sample_rate) {
+            Ok((sample, content_hash)) => {
+                self.params
+                    .sample_hashes
+                    .lock()
+                    .unwrap()
+                    .insert(path.clone(), content_hash);
+                Arc::make_mut(&mut self.samples).insert(path.clone(), sample);
+                SampleStatus::Loaded
+            }
+            Err(SampleStatus::Missing) => match self.load_embedded_sample(&path) {
+                Some(sample) => {
+                    nih_log!("{path:?} is missing, using embedded data");
+                    Arc::make_mut(&mut self.samples).insert(path.clone(), sample);
+                    SampleStatus::Embedded
+                }
+                None => {
+                    Arc::make_mut(&mut self.samples).remove(&path);
+                    SampleStatus::Missing
+                }
+            },
+            Err(status) => {
+                Arc::make_mut(&mut self.samples).remove(&path);
+                status
+            }
+        };
+
+        if status != SampleStatus::Loaded {
+            nih_log!("failed to load {path:?}: {status:?}");
+        }
+        self.sample_status
+            .lock()
+            .unwrap()
+            .insert(path.clone(), status);
+
+        let mut samples = self.params.samples.lock().unwrap();
+        if !samples.iter().any(|sample| sample.path == path) {
+            samples.push(KitSample {
+                path,
+                zone: Zone::default(),
+            });
+        }
+    }
+
+    /// Loads the supported files in a folder that aren't in the sample list yet
+    fn load_folder(&mut self, path: PathBuf, remember: bool) {
+        let path = library::to_portable(&library::resolve(&path));
+        if remember {
+            let mut sample_folders = self.params.sample_folders.lock().unwrap();
+            if !sample_folders.contains(&path) {
+                sample_folders.push(path.clone());
+            }
+        }
+
+        for file_path in sample_pool::supported_files_in(&library::resolve(&path)) {
+            let sample_path = library::to_portable(&file_path);
+            if !self.params.has_sample(&sample_path) {
+                self.load_sample(sample_path);
+            }
+        }
+    }
+
+    fn load_pad(&mut self, pad: Pad) {
+        for path in self.params.sample_paths() {
+            self.remove_sample(&path);
+        }
+        self.params.sample_folders.lock().unwrap().clear();
+
+        for sample in pad.samples {
+            let path = library::to_portable(&library::resolve(&sample.path));
+            // listed with its settings first, loading it keeps the entry
+            if !self.params.has_sample(&path) {
+                self.params.samples.lock().unwrap().push(KitSample {
+                    path: path.clone(),
+                    zone: sample.zone,
+                });
+            }
+            self.load_sample(path);
+        }
+        for path in pad.folders {
+            self.load_folder(path, true);
+        }
+        *self.params.hi_hat.lock().unwrap() = pad.hi_hat;
+        *self.params.choke_pressure.lock().unwrap() = pad.choke_pressure;
+        *self.params.mod_routes.lock().unwrap() = pad.mod_routes;
+        *self.params.routing.lock().unwrap() = pad.routing;
+        *self.params.output_transpose.lock().unwrap() = pad.output_transpose;
+        *self.params.sample_report.lock().unwrap() = pad.sample_report;
+        *self.params.mpe.lock().unwrap() = pad.mpe;
+        *self.params.cc_mappings.lock().unwrap() = pad.cc_mappings.into_iter().collect();
+    }
+
+    fn load_embedded_sample(&self, path: &Path) -> Option<Arc<LoadedSample>> {
+        let embedded = self.params.embedded_samples.lock().unwrap();
+        sample_pool::load_bytes(path, &embedded.get(path)?.data, self.sample_rate).ok()
+    }
+
+    fn remove_sample(&mut self, path: &Path) {
+        let mut samples = self.params.samples.lock().unwrap();
+        if let Some(index) = samples.iter().position(|e| e.path == path) {
+            samples.remove(index);
+        }
+        self.params.embedded_samples.lock().unwrap().remove(path);
+        self.params.sample_hashes.lock().unwrap().remove(path);
+        self.sample_status.lock().unwrap().remove(path);
+        Arc::make_mut(&mut self.samples).remove(path);
+        if let Some(watcher) = &mut self.watcher {
+            watcher.unwatch(path);
+        }
+    }
+
+    /// Points a sample at a new path, keeping its position in the sample list
+    fn relink_sample(&mut self, old_path: &Path, new_path: &Path) {
+        let new_path = library::to_portable(new_path);
+        if self.rename_sample(old_path, &new_path) {
+            self.load_sample(new_path);
+        }
+    }
+
+    /// Replaces a path in the sample list and everything keyed by it, without reloading the
+    /// sample. Returns `false` if the old path isn't in the sample list.
+    fn rename_sample(&mut self, old_path: &Path, new_path: &Path) -> bool {
+        {
+            let mut samples = self.params.samples.lock().unwrap();
+            let Some(index) = samples.iter().position(|e| e.path == old_path) else {
+                return false;
+            };
+            if samples.iter().any(|e| e.path == new_path) {
+                samples.remove(index);
+            } else {
+                samples[index].path = new_path.to_path_buf();
+            }
+        }
+
+        let mut embedded_samples = self.params.embedded_samples.lock().unwrap();
+        if let Some(embedded) = embedded_samples.remove(old_path) {
+            embedded_samples.insert(new_path.to_path_buf(), embedded);
+        }
+        let mut sample_hashes = self.params.sample_hashes.lock().unwrap();
+        if let Some(content_hash) = sample_hashes.remove(old_path) {
+            sample_hashes.insert(new_path.to_path_buf(), content_hash);
+        }
+        self.sample_status.lock().unwrap().remove(old_path);
+        if let Some(watcher) = &mut self.watcher {
+            watcher.unwatch(old_path);
+        }
+        let samples = Arc::make_mut(&mut self.samples);
+        if let Some(sample) = samples.remove(old_path) {
+            samples.insert(new_path.to_path_buf(), sample);
+        }
+
+        true
+    }
+}
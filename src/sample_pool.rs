@@ -0,0 +1,312 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, OnceLock, Weak},
+};
+
+use crate::{resample, uninterleave, LoadedSample};
+
+/// File extensions of the formats that can be decoded
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["wav", "flac"];
+
+/// Whether a sample from the sample list could be loaded, shown next to it in the editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleStatus {
+    Loaded,
+    /// The file is missing, but a copy was embedded in the plugin state
+    Embedded,
+    Missing,
+    DecodeError,
+    UnsupportedFormat,
+}
+
+impl SampleStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SampleStatus::Loaded => "",
+            SampleStatus::Embedded => "Embedded",
+            SampleStatus::Missing => "Missing",
+            SampleStatus::DecodeError => "Decode error",
+            SampleStatus::UnsupportedFormat => "Unsupported",
+        }
+    }
+
+    /// Whether the file on disk couldn't be found and should be relinked
+    pub fn is_missing(&self) -> bool {
+        matches!(self, SampleStatus::Embedded | SampleStatus::Missing)
+    }
+}
+
+/// Identifies a decoded sample in the pool. The content hash makes sure a file that was changed on
+/// disk doesn't get served from the cache, and the sample rate is included because every sample
+/// gets resampled to the rate of the instance that loads it.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    path: PathBuf,
+    content_hash: u64,
+    sample_rate: u32,
+}
+
+/// A sample in the pool, or a marker for a sample that's being decoded right now
+enum PoolEntry {
+    Loaded(Weak<LoadedSample>),
+    /// Another thread is decoding the sample, and notifies the pool when it's done
+    Decoding,
+}
+
+/// Process-wide cache of decoded samples. Only weak references are kept here, so a sample is freed
+/// as soon as the last plugin instance using it drops its `Arc`.
+struct Pool {
+    entries: Mutex<HashMap<PoolKey, PoolEntry>>,
+    /// Notified whenever a sample finished decoding
+    decoded: Condvar,
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(|| Pool {
+        entries: Mutex::new(HashMap::new()),
+        decoded: Condvar::new(),
+    })
+}
+
+/// Removes the decoding marker of a sample and wakes up the threads waiting for it, also when
+/// decoding panicked
+struct DecodingGuard {
+    key: PoolKey,
+}
+
+impl Drop for DecodingGuard {
+    fn drop(&mut self) {
+        let pool = pool();
+        let mut entries = pool.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if matches!(entries.get(&self.key), Some(PoolEntry::Decoding)) {
+            entries.remove(&self.key);
+        }
+        drop(entries);
+        pool.decoded.notify_all();
+    }
+}
+
+/// Loads the sample at the given path resampled to `sample_rate`, sharing the decoded data with any
+/// other instance that already loaded the same file at the same rate. Also returns the hash of the
+/// file's contents.
+pub fn load(path: &Path, sample_rate: f32) -> Result<(Arc<LoadedSample>, u64), SampleStatus> {
+    let bytes = std::fs::read(path).map_err(|_| SampleStatus::Missing)?;
+    let content_hash = content_hash(&bytes);
+
+    Ok((
+        load_hashed(path, &bytes, content_hash, sample_rate)?,
+        content_hash,
+    ))
+}
+
+/// Like [`load`], but with the file's contents already in memory. This is used for samples
+/// embedded in the plugin state.
+pub fn load_bytes(
+    path: &Path,
+    bytes: &[u8],
+    sample_rate: f32,
+) -> Result<Arc<LoadedSample>, SampleStatus> {
+    load_hashed(path, bytes, content_hash(bytes), sample_rate)
+}
+
+fn load_hashed(
+    path: &Path,
+    bytes: &[u8],
+    content_hash: u64,
+    sample_rate: f32,
+) -> Result<Arc<LoadedSample>, SampleStatus> {
+    let key = PoolKey {
+        path: path.to_path_buf(),
+        content_hash,
+        sample_rate: sample_rate as u32,
+    };
+
+    // the lock is only held to look up the sample, so loading one sample never waits for the
+    // decoding of another. Instances loading the same sample at the same time wait for the first
+    // one to decode it instead of all decoding it.
+    let pool = pool();
+    let mut entries = pool.entries.lock().unwrap();
+    loop {
+        let decoding = match entries.get(&key) {
+            Some(PoolEntry::Loaded(sample)) => {
+                if let Some(sample) = sample.upgrade() {
+                    return Ok(sample);
+                }
+                false
+            }
+            Some(PoolEntry::Decoding) => true,
+            None => false,
+        };
+        if !decoding {
+            break;
+        }
+        entries = pool.decoded.wait(entries).unwrap();
+    }
+    entries.insert(key.clone(), PoolEntry::Decoding);
+    drop(entries);
+
+    let guard = DecodingGuard { key };
+    let sample = Arc::new(decode(bytes, sample_rate)?);
+
+    let mut entries = pool.entries.lock().unwrap();
+    // get rid of entries that aren't used by any instance anymore
+    entries.retain(|_, entry| match entry {
+        PoolEntry::Loaded(sample) => sample.strong_count() > 0,
+        PoolEntry::Decoding => true,
+    });
+    entries.insert(guard.key.clone(), PoolEntry::Loaded(Arc::downgrade(&sample)));
+    drop(entries);
+    drop(guard);
+
+    Ok(sample)
+}
+
+/// Decodes a wav or flac file and resamples it to `sample_rate` if needed
+pub fn decode(bytes: &[u8], sample_rate: f32) -> Result<LoadedSample, SampleStatus> {
+    let decoded = if bytes.starts_with(b"fLaC") {
+        decode_flac(Cursor::new(bytes))
+    } else if bytes.starts_with(b"RIFF") || bytes.starts_with(b"RIFX") {
+        decode_wav(Cursor::new(bytes))
+    } else {
+        return Err(SampleStatus::UnsupportedFormat);
+    };
+    let (interleaved_samples, channels, file_sample_rate) =
+        decoded.ok_or(SampleStatus::DecodeError)?;
+    if channels == 0 || interleaved_samples.is_empty() {
+        return Err(SampleStatus::DecodeError);
+    }
+
+    let mut samples = uninterleave(interleaved_samples, channels);
+
+    // resample if needed
+    if file_sample_rate != sample_rate {
+        samples = resample(samples, file_sample_rate, sample_rate);
+    }
+
+    Ok(samples)
+}
+
+/// Returns the interleaved samples, channel count and sample rate of a wav file
+fn decode_wav<R: Read>(reader: R) -> Option<(Vec<f32>, usize, f32)> {
+    let mut reader = hound::WavReader::new(reader).ok()?;
+    let spec = reader.spec();
+
+    let interleaved_samples = match spec.sample_format {
+        hound::SampleFormat::Int => reader
+            .samples::<i32>()
+            .map(|s| (s.unwrap_or_default() as f32 * 256.0) / i32::MAX as f32)
+            .collect::<Vec<f32>>(),
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.unwrap_or_default())
+            .collect::<Vec<f32>>(),
+    };
+
+    Some((
+        interleaved_samples,
+        spec.channels as usize,
+        spec.sample_rate as f32,
+    ))
+}
+
+/// Returns the interleaved samples, channel count and sample rate of a flac file
+fn decode_flac<R: Read>(reader: R) -> Option<(Vec<f32>, usize, f32)> {
+    let mut reader = claxon::FlacReader::new(reader).ok()?;
+    let info = reader.streaminfo();
+    let scale = (1u32 << (info.bits_per_sample - 1)) as f32;
+
+    let interleaved_samples = reader
+        .samples()
+        .map(|s| s.unwrap_or_default() as f32 / scale)
+        .collect::<Vec<f32>>();
+
+    Some((
+        interleaved_samples,
+        info.channels as usize,
+        info.sample_rate as f32,
+    ))
+}
+
+/// Reads the sample rate from a file's header without decoding it
+pub fn file_sample_rate(path: &Path) -> Option<f32> {
+    if let Ok(reader) = hound::WavReader::open(path) {
+        return Some(reader.spec().sample_rate as f32);
+    }
+    let reader = claxon::FlacReader::open(path).ok()?;
+    Some(reader.streaminfo().sample_rate as f32)
+}
+
+/// Reads the length of a file in seconds from its header without decoding it
+pub fn file_duration(path: &Path) -> Option<f32> {
+    if let Ok(reader) = hound::WavReader::open(path) {
+        return Some(reader.duration() as f32 / reader.spec().sample_rate as f32);
+    }
+    let reader = claxon::FlacReader::open(path).ok()?;
+    let info = reader.streaminfo();
+    Some(info.samples? as f32 / info.sample_rate as f32)
+}
+
+/// 64 bit FNV-1a hash of a file's contents. This is stable across builds and platforms, unlike the
+/// std `DefaultHasher`.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Recursively lists all files with a supported extension in the given directory
+pub fn supported_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    walk_supported_files(dir, &mut |path| {
+        files.push(path);
+        ControlFlow::Continue(())
+    });
+    files
+}
+
+/// Calls `visit` for every file with a supported extension in the given directory and its
+/// subdirectories in sorted order, until it breaks. Symlinked directories are followed, but every
+/// directory is only visited once so links pointing back up the tree don't loop forever.
+pub fn walk_supported_files(dir: &Path, visit: &mut impl FnMut(PathBuf) -> ControlFlow<()>) {
+    let _ = walk(dir, &mut HashSet::new(), visit);
+}
+
+fn walk(
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    visit: &mut impl FnMut(PathBuf) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    let Ok(canonical_dir) = dir.canonicalize() else {
+        return ControlFlow::Continue(());
+    };
+    if !visited.insert(canonical_dir) {
+        return ControlFlow::Continue(());
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return ControlFlow::Continue(());
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            walk(&path, visited, visit)?;
+        } else if is_supported(&path) {
+            visit(path)?;
+        }
+    }
+
+    ControlFlow::Continue(())
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+}
@@ -0,0 +1,100 @@
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A MIDI event sent every time a sample is triggered, reporting which one was picked so it can be
+/// recorded or drive lights and visuals. Samples are numbered by their position in the sample list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+// tagged internally, TOML kit files can't hold struct variants otherwise
+#[serde(tag = "kind")]
+pub enum SampleReport {
+    #[default]
+    Off,
+    /// A note whose number is the sample's index, with the velocity of the note that triggered it
+    Note { channel: u8 },
+    /// A CC whose value is the sample's index
+    Cc { channel: u8, cc: u8 },
+}
+
+/// Channel used when turning the report on, the last one to stay out of the way of drum channels
+const DEFAULT_CHANNEL: u8 = 15;
+
+/// An undefined controller, used when switching to CC reports
+const DEFAULT_CC: u8 = 102;
+
+impl SampleReport {
+    /// Switches between off, notes and CCs, keeping the channel
+    pub fn next(&self) -> Self {
+        match *self {
+            SampleReport::Off => SampleReport::Note {
+                channel: DEFAULT_CHANNEL,
+            },
+            SampleReport::Note { channel } => SampleReport::Cc {
+                channel,
+                cc: DEFAULT_CC,
+            },
+            SampleReport::Cc { .. } => SampleReport::Off,
+        }
+    }
+
+    pub fn with_channel(&self, channel: u8) -> Self {
+        match *self {
+            SampleReport::Off => SampleReport::Off,
+            SampleReport::Note { .. } => SampleReport::Note { channel },
+            SampleReport::Cc { cc, .. } => SampleReport::Cc { channel, cc },
+        }
+    }
+
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            SampleReport::Off => None,
+            SampleReport::Note { channel } | SampleReport::Cc { channel, .. } => Some(channel),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            SampleReport::Off => "Off".into(),
+            SampleReport::Note { channel } => format!("Note on channel {}", channel + 1),
+            SampleReport::Cc { channel, cc } => format!("CC {cc} on channel {}", channel + 1),
+        }
+    }
+
+    /// Sends the report for the sample at `index` in the sample list. Notes are released right
+    /// away, as only their number matters.
+    pub fn send<P: Plugin<SysExMessage = ()>>(
+        &self,
+        context: &mut impl ProcessContext<P>,
+        timing: u32,
+        index: usize,
+        velocity: f32,
+    ) {
+        let index = index.min(127) as u8;
+        match *self {
+            SampleReport::Off => {}
+            SampleReport::Note { channel } => {
+                context.send_event(NoteEvent::NoteOn {
+                    timing,
+                    voice_id: None,
+                    channel,
+                    note: index,
+                    velocity,
+                });
+                context.send_event(NoteEvent::NoteOff {
+                    timing,
+                    voice_id: None,
+                    channel,
+                    note: index,
+                    velocity: 0.0,
+                });
+            }
+            SampleReport::Cc { channel, cc } => {
+                context.send_event(NoteEvent::MidiCC {
+                    timing,
+                    channel,
+                    cc,
+                    value: index as f32 / 127.0,
+                });
+            }
+        }
+    }
+}
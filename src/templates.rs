@@ -0,0 +1,202 @@
+use std::path::PathBuf;
+
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::kit::{Kit, Pad};
+
+/// The General MIDI percussion key map
+const GENERAL_MIDI: &[(u8, &str)] = &[
+    (35, "Acoustic Bass Drum"),
+    (36, "Bass Drum 1"),
+    (37, "Side Stick"),
+    (38, "Acoustic Snare"),
+    (39, "Hand Clap"),
+    (40, "Electric Snare"),
+    (41, "Low Floor Tom"),
+    (42, "Closed Hi-Hat"),
+    (43, "High Floor Tom"),
+    (44, "Pedal Hi-Hat"),
+    (45, "Low Tom"),
+    (46, "Open Hi-Hat"),
+    (47, "Low-Mid Tom"),
+    (48, "Hi-Mid Tom"),
+    (49, "Crash Cymbal 1"),
+    (50, "High Tom"),
+    (51, "Ride Cymbal 1"),
+    (52, "Chinese Cymbal"),
+    (53, "Ride Bell"),
+    (54, "Tambourine"),
+    (55, "Splash Cymbal"),
+    (56, "Cowbell"),
+    (57, "Crash Cymbal 2"),
+    (58, "Vibraslap"),
+    (59, "Ride Cymbal 2"),
+    (60, "Hi Bongo"),
+    (61, "Low Bongo"),
+    (62, "Mute Hi Conga"),
+    (63, "Open Hi Conga"),
+    (64, "Low Conga"),
+    (65, "High Timbale"),
+    (66, "Low Timbale"),
+    (67, "High Agogo"),
+    (68, "Low Agogo"),
+    (69, "Cabasa"),
+    (70, "Maracas"),
+    (71, "Short Whistle"),
+    (72, "Long Whistle"),
+    (73, "Short Guiro"),
+    (74, "Long Guiro"),
+    (75, "Claves"),
+    (76, "Hi Wood Block"),
+    (77, "Low Wood Block"),
+    (78, "Mute Cuica"),
+    (79, "Open Cuica"),
+    (80, "Mute Triangle"),
+    (81, "Open Triangle"),
+];
+
+/// A drum map, naming the trigger notes of a kit's pads. Besides the General MIDI map, templates
+/// can be defined in `nih-sampler/templates.toml` in the user's config directory:
+///
+/// ```toml
+/// [[template]]
+/// name = "My e-kit"
+///
+/// [[template.pad]]
+/// name = "Kick"
+/// note = 36
+/// # pads with one of these names are mapped to this pad when remapping a kit
+/// aliases = ["Bass Drum 1", "Acoustic Bass Drum"]
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    #[serde(default, rename = "pad")]
+    pub pads: Vec<TemplatePad>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TemplatePad {
+    pub name: String,
+    pub note: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct TemplateFile {
+    #[serde(default)]
+    template: Vec<Template>,
+}
+
+impl Template {
+    pub fn general_midi() -> Self {
+        Self {
+            name: "General MIDI".into(),
+            pads: GENERAL_MIDI
+                .iter()
+                .map(|&(note, name)| TemplatePad {
+                    name: name.into(),
+                    note,
+                    aliases: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    /// The pad triggered by `note`
+    pub fn pad_for_note(&self, note: u8) -> Option<&TemplatePad> {
+        self.pads.iter().find(|pad| pad.note == note)
+    }
+
+    /// The pad with the given name or alias, ignoring case
+    pub fn pad_for_name(&self, name: &str) -> Option<&TemplatePad> {
+        self.pads.iter().find(|pad| {
+            std::iter::once(&pad.name)
+                .chain(&pad.aliases)
+                .any(|pad_name| pad_name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// An empty kit with a pad for every note in the template
+    pub fn new_kit(&self, name: String) -> Kit {
+        Kit {
+            name,
+            template: Some(self.name.clone()),
+            pads: self
+                .pads
+                .iter()
+                .map(|pad| Pad::new(pad.name.clone(), pad.note))
+                .collect(),
+        }
+    }
+
+    /// Moves the pads of a kit to the notes this template uses for them. Pads are matched by the
+    /// name the kit's own template gives their note, falling back to the pads' names. Returns
+    /// warnings about pads that couldn't be mapped.
+    pub fn remap(&self, kit: &mut Kit, templates: &[Template]) -> Vec<String> {
+        let from = kit
+            .template
+            .as_ref()
+            .and_then(|name| templates.iter().find(|template| template.name == *name));
+
+        let mut warnings = vec![];
+        for pad in &mut kit.pads {
+            let from_pad = from.and_then(|from| from.pad_for_note(pad.note));
+            let to_pad = from_pad
+                .and_then(|from_pad| {
+                    std::iter::once(&from_pad.name)
+                        .chain(&from_pad.aliases)
+                        .find_map(|name| self.pad_for_name(name))
+                })
+                .or_else(|| self.pad_for_name(&pad.name));
+
+            match to_pad {
+                Some(to_pad) => {
+                    pad.name = to_pad.name.clone();
+                    pad.note = to_pad.note;
+                }
+                None => warnings.push(format!(
+                    "{} ({}) has no matching pad in {}",
+                    pad.name, pad.note, self.name
+                )),
+            }
+        }
+
+        let mut notes = kit.pads.iter().map(|pad| pad.note).collect::<Vec<_>>();
+        notes.sort_unstable();
+        notes.dedup();
+        if notes.len() < kit.pads.len() {
+            warnings.push("several pads now use the same note".into());
+        }
+
+        kit.template = Some(self.name.clone());
+        warnings
+    }
+}
+
+pub fn templates_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("nih-sampler")
+            .join("templates.toml"),
+    )
+}
+
+/// The General MIDI template followed by the user's templates
+pub fn all() -> Vec<Template> {
+    let user_templates = templates_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|contents| {
+            toml::from_str::<TemplateFile>(&contents).unwrap_or_else(|e| {
+                nih_warn!("invalid templates file: {e}");
+                TemplateFile::default()
+            })
+        })
+        .unwrap_or_default();
+
+    std::iter::once(Template::general_midi())
+        .chain(user_templates.template)
+        .collect()
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use nih_plug::prelude::*;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::{sample_pool, LoadedSample};
+
+/// A sample that changed on disk and was decoded again on the watcher's thread
+pub struct ReloadedSample {
+    /// The path as stored in the sample list
+    pub path: PathBuf,
+    pub sample: Arc<LoadedSample>,
+    pub content_hash: u64,
+}
+
+/// Watches the files in the sample list and reloads them in the background when they change.
+/// Reloaded samples are sent to the sample loader through a ring buffer, and `reloads_pending` is
+/// set so the audio thread knows to have them applied.
+pub struct SampleWatcher {
+    watcher: notify::RecommendedWatcher,
+    /// Maps the resolved file paths to the paths in the sample list
+    watched_files: Arc<Mutex<HashMap<PathBuf, PathBuf>>>,
+    /// The parent directories of the watched files and how many files they contain. Directories
+    /// are watched instead of the files themselves, as many editors save by replacing the file.
+    watched_dirs: HashMap<PathBuf, usize>,
+}
+
+impl SampleWatcher {
+    pub fn new(
+        sample_rate: f32,
+        reloads_pending: Arc<AtomicBool>,
+    ) -> Option<(Self, rtrb::Consumer<ReloadedSample>)> {
+        let (mut producer, consumer) = rtrb::RingBuffer::new(64);
+        let watched_files = Arc::new(Mutex::new(HashMap::<PathBuf, PathBuf>::new()));
+
+        let handler_watched_files = watched_files.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+
+            for file_path in event.paths {
+                let Some(path) = handler_watched_files.lock().unwrap().get(&file_path).cloned()
+                else {
+                    continue;
+                };
+
+                // files that are still being written fail to decode, the next event will pick
+                // them up once they're complete
+                if let Ok((sample, content_hash)) = sample_pool::load(&file_path, sample_rate) {
+                    nih_log!("reloading {path:?}");
+                    let _ = producer.push(ReloadedSample {
+                        path,
+                        sample,
+                        content_hash,
+                    });
+                    reloads_pending.store(true, Ordering::Relaxed);
+                }
+            }
+        })
+        .map_err(|e| nih_warn!("failed to create file watcher: {e}"))
+        .ok()?;
+
+        Some((
+            Self {
+                watcher,
+                watched_files,
+                watched_dirs: HashMap::new(),
+            },
+            consumer,
+        ))
+    }
+
+    /// Starts watching `file_path`, reporting changes under the sample list's `path`
+    pub fn watch(&mut self, file_path: &Path, path: &Path) {
+        let previous = self
+            .watched_files
+            .lock()
+            .unwrap()
+            .insert(file_path.to_path_buf(), path.to_path_buf());
+        if previous.is_some() {
+            return;
+        }
+
+        let Some(dir) = file_path.parent() else {
+            return;
+        };
+        let count = self.watched_dirs.entry(dir.to_path_buf()).or_insert(0);
+        if *count == 0 {
+            if let Err(e) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                nih_warn!("failed to watch {dir:?}: {e}");
+            }
+        }
+        *count += 1;
+    }
+
+    /// Stops watching the file with the given sample list path
+    pub fn unwatch(&mut self, path: &Path) {
+        let mut watched_files = self.watched_files.lock().unwrap();
+        let Some(file_path) = watched_files
+            .iter()
+            .find(|(_, watched_path)| *watched_path == path)
+            .map(|(file_path, _)| file_path.clone())
+        else {
+            return;
+        };
+        watched_files.remove(&file_path);
+
+        let Some(dir) = file_path.parent() else {
+            return;
+        };
+        if let Some(count) = self.watched_dirs.get_mut(dir) {
+            *count -= 1;
+            if *count == 0 {
+                self.watched_dirs.remove(dir);
+                let _ = self.watcher.unwatch(dir);
+            }
+        }
+    }
+}